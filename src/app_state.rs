@@ -1,14 +1,44 @@
-use aws_sdk_cloudwatchlogs::Client;
+use std::sync::Arc;
+
 use datafusion::prelude::SessionContext;
+use tokio::runtime::Handle;
+
+use crate::config::Config;
+use crate::log_source::LogSource;
+use crate::logging_table::{LoggingTableRefresher, TableStatuses};
 
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub ctx: SessionContext,
-    pub client: Client,
+    pub source: Arc<dyn LogSource>,
+    // runtime executing the queries, see utils::datafusion::build_query_runtime
+    pub query_runtime: Handle,
+    pub statuses: TableStatuses,
+    // refreshes the snapshot table on demand, None in lazy mode
+    pub refresher: Option<LoggingTableRefresher>,
 }
 
 impl AppState {
-    pub fn new(ctx: SessionContext, client: Client) -> Self {
-        Self { ctx, client }
+    pub fn new(
+        config: Arc<Config>,
+        ctx: SessionContext,
+        source: Arc<dyn LogSource>,
+        query_runtime: Handle,
+        statuses: TableStatuses,
+    ) -> Self {
+        Self {
+            config,
+            ctx,
+            source,
+            query_runtime,
+            statuses,
+            refresher: None,
+        }
+    }
+
+    pub fn with_refresher(mut self, refresher: LoggingTableRefresher) -> Self {
+        self.refresher = Some(refresher);
+        self
     }
 }
@@ -0,0 +1,37 @@
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Missing credentials, send an x-api-key header or an Authorization bearer token")]
+    MissingCredentials,
+
+    #[error("Malformed Authorization header, expected Bearer <token>")]
+    MalformedHeader,
+
+    #[error("Unknown API key")]
+    UnknownApiKey,
+
+    #[error("Bearer tokens are not accepted, no JWKS is configured")]
+    JwtNotAccepted,
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    #[error("Token expired")]
+    TokenExpired,
+
+    #[error("Invalid SHA-256 hash of the API key {0}")]
+    InvalidApiKeyHash(String),
+
+    #[error("Failed to read the JWKS file {0}")]
+    ReadJwks(PathBuf, #[source] IoError),
+
+    #[error("Invalid JWKS file {0}")]
+    ParseJwks(PathBuf, #[source] serde_json::Error),
+
+    #[error("Invalid JWK {0}: {1}")]
+    InvalidJwk(String, String),
+}
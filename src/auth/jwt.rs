@@ -0,0 +1,575 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::signature::{
+    self, RsaParameters, RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::error::AuthError;
+use crate::config::AuthConfig;
+
+// "none" and the HMAC algorithms are rejected, the keys are public
+const ALGORITHMS: [&str; 8] = [
+    "RS256", "RS384", "RS512", "PS256", "PS384", "PS512", "ES256", "ES384",
+];
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+// RSA keys use n and e, EC keys crv, x and y
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+    // critical extensions must be understood, none are
+    crit: Option<Value>,
+}
+
+enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    // uncompressed point, 0x04 followed by x and y
+    Ec { crv: String, point: Vec<u8> },
+}
+
+struct VerifyingKey {
+    kid: Option<String>,
+    // the only algorithm accepted for the key when the JWK declares one
+    alg: Option<String>,
+    key: PublicKey,
+}
+
+/// Checks bearer JWTs against the keys of a local JWKS file, read once at startup.
+pub struct JwtValidator {
+    keys: Vec<VerifyingKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: Duration,
+}
+
+impl JwtValidator {
+    pub fn new(jwks_file: &Path, config: &AuthConfig) -> Result<Self, AuthError> {
+        let content = std::fs::read_to_string(jwks_file)
+            .map_err(|e| AuthError::ReadJwks(jwks_file.to_path_buf(), e))?;
+        let jwks: JwkSet = serde_json::from_str(&content)
+            .map_err(|e| AuthError::ParseJwks(jwks_file.to_path_buf(), e))?;
+        let keys = jwks
+            .keys
+            .into_iter()
+            // encryption keys can not check signatures
+            .filter(|jwk| jwk.usage.as_deref().is_none_or(|usage| usage == "sig"))
+            .map(VerifyingKey::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            keys,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            leeway: Duration::from_secs(config.leeway_secs),
+        })
+    }
+
+    /// Returns the claims of a token signed by one of the keys, once `exp`, `nbf`, `iss` and
+    /// `aud` are checked. Tokens without `exp` or with critical header parameters are rejected.
+    pub fn validate(&self, token: &str) -> Result<Map<String, Value>, AuthError> {
+        let parts: Vec<_> = token.split('.').collect();
+        let [header, payload, signature] = parts[..] else {
+            return Err(invalid("expected three dot separated parts"));
+        };
+        let jwt_header: JwtHeader = serde_json::from_slice(&decode(header)?)
+            .map_err(|e| invalid(&format!("header: {e}")))?;
+        if !ALGORITHMS.contains(&jwt_header.alg.as_str()) {
+            return Err(invalid(&format!(
+                "unsupported algorithm {}",
+                jwt_header.alg
+            )));
+        }
+        if jwt_header.crit.is_some() {
+            return Err(invalid("unsupported critical header parameters"));
+        }
+        let signature = decode(signature)?;
+        // the signature covers the encoded header and payload
+        let message = &token[..header.len() + payload.len() + 1];
+
+        let verified = self
+            .keys
+            .iter()
+            .filter(|key| jwt_header.kid.is_none() || key.kid == jwt_header.kid)
+            .filter(|key| key.alg.as_ref().is_none_or(|alg| *alg == jwt_header.alg))
+            .any(|key| key.verify(&jwt_header.alg, message.as_bytes(), &signature));
+        if !verified {
+            return Err(invalid("signature does not match any key"));
+        }
+
+        let claims: Map<String, Value> = serde_json::from_slice(&decode(payload)?)
+            .map_err(|e| invalid(&format!("claims: {e}")))?;
+        self.validate_claims(&claims)?;
+        Ok(claims)
+    }
+
+    fn validate_claims(&self, claims: &Map<String, Value>) -> Result<(), AuthError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let leeway = self.leeway.as_secs_f64();
+        let exp = numeric_date(claims, "exp")?.ok_or_else(|| invalid("missing exp claim"))?;
+        if now > exp + leeway {
+            return Err(AuthError::TokenExpired);
+        }
+        if let Some(nbf) = numeric_date(claims, "nbf")? {
+            if now + leeway < nbf {
+                return Err(invalid("not valid yet"));
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+                return Err(invalid("unexpected issuer"));
+            }
+        }
+        if let Some(audience) = &self.audience {
+            // a single audience or an array of them
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(invalid("unexpected audience"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VerifyingKey {
+    // false as well when the algorithm does not suit the key
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        match &self.key {
+            PublicKey::Rsa { n, e } => {
+                let params: &RsaParameters = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    "PS512" => &signature::RSA_PSS_2048_8192_SHA512,
+                    _ => return false,
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(params, message, signature)
+                    .is_ok()
+            }
+            PublicKey::Ec { crv, point } => {
+                let algorithm: &'static dyn VerificationAlgorithm = match (alg, crv.as_str()) {
+                    ("ES256", "P-256") => &signature::ECDSA_P256_SHA256_FIXED,
+                    ("ES384", "P-384") => &signature::ECDSA_P384_SHA384_FIXED,
+                    _ => return false,
+                };
+                UnparsedPublicKey::new(algorithm, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+impl TryFrom<Jwk> for VerifyingKey {
+    type Error = AuthError;
+
+    fn try_from(jwk: Jwk) -> Result<Self, Self::Error> {
+        let kid = jwk.kid.clone().unwrap_or_default();
+        if let Some(alg) = &jwk.alg {
+            if !ALGORITHMS.contains(&alg.as_str()) {
+                return Err(AuthError::InvalidJwk(
+                    kid,
+                    format!("unsupported algorithm {alg}"),
+                ));
+            }
+        }
+        let field = |name: &str, value: Option<String>| {
+            let value = value
+                .ok_or_else(|| AuthError::InvalidJwk(kid.clone(), format!("missing {name}")))?;
+            URL_SAFE_NO_PAD
+                .decode(value)
+                .map_err(|e| AuthError::InvalidJwk(kid.clone(), format!("{name}: {e}")))
+        };
+        let key = match jwk.kty.as_str() {
+            // ring rejects the leading zeros some issuers keep
+            "RSA" => PublicKey::Rsa {
+                n: strip_leading_zeros(field("n", jwk.n)?),
+                e: strip_leading_zeros(field("e", jwk.e)?),
+            },
+            "EC" => {
+                let crv = jwk.crv.unwrap_or_default();
+                if !matches!(crv.as_str(), "P-256" | "P-384") {
+                    return Err(AuthError::InvalidJwk(
+                        kid,
+                        format!("unsupported curve {crv:?}"),
+                    ));
+                }
+                let mut point = vec![0x04];
+                point.extend(field("x", jwk.x)?);
+                point.extend(field("y", jwk.y)?);
+                PublicKey::Ec { crv, point }
+            }
+            kty => {
+                return Err(AuthError::InvalidJwk(
+                    kid,
+                    format!("unsupported key type {kty}"),
+                ))
+            }
+        };
+        Ok(Self {
+            kid: jwk.kid,
+            alg: jwk.alg,
+            key,
+        })
+    }
+}
+
+fn strip_leading_zeros(mut bytes: Vec<u8>) -> Vec<u8> {
+    let zeros = bytes.iter().take_while(|x| **x == 0).count();
+    bytes.drain(..zeros);
+    bytes
+}
+
+// NumericDate claims may be fractional (RFC 7519 section 2)
+fn numeric_date(claims: &Map<String, Value>, name: &str) -> Result<Option<f64>, AuthError> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{name} claim is not a number"))),
+    }
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, AuthError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| invalid(&format!("base64: {e}")))
+}
+
+fn invalid(message: &str) -> AuthError {
+    AuthError::InvalidToken(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, RsaEncoding, RsaKeyPair},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const RSA_KEY: &[u8] = include_bytes!("../../fixtures/auth/rsa_2048.der");
+
+    struct Keys {
+        rsa: RsaKeyPair,
+        ec: EcdsaKeyPair,
+    }
+
+    impl Keys {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let algorithm = &signature::ECDSA_P256_SHA256_FIXED_SIGNING;
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(algorithm, &rng).unwrap();
+            Self {
+                rsa: RsaKeyPair::from_der(RSA_KEY).unwrap(),
+                ec: EcdsaKeyPair::from_pkcs8(algorithm, pkcs8.as_ref(), &rng).unwrap(),
+            }
+        }
+
+        fn rsa_jwk(&self, kid: &str, alg: Option<&str>) -> Value {
+            let components = RsaPublicKeyComponents::<Vec<u8>>::from(self.rsa.public());
+            json!({
+                "kty": "RSA",
+                "kid": kid,
+                "alg": alg,
+                "n": URL_SAFE_NO_PAD.encode(components.n),
+                "e": URL_SAFE_NO_PAD.encode(components.e),
+            })
+        }
+
+        fn ec_jwk(&self, kid: &str) -> Value {
+            let point = self.ec.public_key().as_ref();
+            json!({
+                "kty": "EC",
+                "kid": kid,
+                "crv": "P-256",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            })
+        }
+
+        fn rsa_token(
+            &self,
+            padding: &'static dyn RsaEncoding,
+            header: Value,
+            claims: Value,
+        ) -> String {
+            let message = message(&header, &claims);
+            let mut signature = vec![0; self.rsa.public().modulus_len()];
+            self.rsa
+                .sign(
+                    padding,
+                    &SystemRandom::new(),
+                    message.as_bytes(),
+                    &mut signature,
+                )
+                .unwrap();
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+        }
+
+        fn ec_token(&self, header: Value, claims: Value) -> String {
+            let message = message(&header, &claims);
+            let signature = self
+                .ec
+                .sign(&SystemRandom::new(), message.as_bytes())
+                .unwrap();
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+        }
+    }
+
+    fn message(header: &Value, claims: &Value) -> String {
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claims() -> Value {
+        json!({ "sub": "alice", "exp": now() + 300, "iss": "issuer", "aud": "api" })
+    }
+
+    fn validator(keys: Vec<Value>) -> JwtValidator {
+        let config = AuthConfig {
+            jwt_issuer: Some("issuer".to_string()),
+            jwt_audience: Some("api".to_string()),
+            leeway_secs: 0,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, json!({ "keys": keys }).to_string()).unwrap();
+        let validator = JwtValidator::new(&path, &config);
+        std::fs::remove_file(&path).unwrap();
+        validator.unwrap()
+    }
+
+    fn assert_invalid(res: Result<Map<String, Value>, AuthError>, expected: &str) {
+        match res {
+            Err(AuthError::InvalidToken(message)) => {
+                assert!(message.contains(expected), "{message}")
+            }
+            res => panic!("expected an invalid token, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_tokens() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.rsa_jwk("rsa", Some("RS256")), keys.ec_jwk("ec")]);
+
+        let header = json!({ "alg": "RS256", "kid": "rsa" });
+        let token = keys.rsa_token(&signature::RSA_PKCS1_SHA256, header, claims());
+        assert_eq!(validator.validate(&token).unwrap()["sub"], "alice");
+
+        let token = keys.ec_token(json!({ "alg": "ES256", "kid": "ec" }), claims());
+        assert_eq!(validator.validate(&token).unwrap()["sub"], "alice");
+
+        // without kid every key is tried
+        let token = keys.ec_token(json!({ "alg": "ES256" }), claims());
+        assert!(validator.validate(&token).is_ok());
+    }
+
+    #[test]
+    fn binds_the_algorithm_to_the_key() {
+        let keys = Keys::new();
+        let header = json!({ "alg": "PS256", "kid": "rsa" });
+        let token = keys.rsa_token(&signature::RSA_PSS_SHA256, header, claims());
+
+        // valid PS256 signature, but the key is declared RS256
+        let validator_rs256 = validator(vec![keys.rsa_jwk("rsa", Some("RS256"))]);
+        assert_invalid(validator_rs256.validate(&token), "signature");
+
+        let validator_ps256 = validator(vec![keys.rsa_jwk("rsa", Some("PS256"))]);
+        assert!(validator_ps256.validate(&token).is_ok());
+
+        // keys without alg accept any algorithm suiting them
+        let validator_any = validator(vec![keys.rsa_jwk("rsa", None)]);
+        assert!(validator_any.validate(&token).is_ok());
+    }
+
+    #[test]
+    fn rejects_keys_declaring_unsupported_algorithms() {
+        let keys = Keys::new();
+        let config = AuthConfig::default();
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        let jwks = json!({ "keys": [keys.rsa_jwk("rsa", Some("HS256"))] });
+        std::fs::write(&path, jwks.to_string()).unwrap();
+        let res = JwtValidator::new(&path, &config);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(AuthError::InvalidJwk(kid, _)) if kid == "rsa"));
+    }
+
+    #[test]
+    fn rejects_critical_header_parameters() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+        let header = json!({ "alg": "ES256", "kid": "ec", "crit": ["exp"], "exp": 0 });
+        let token = keys.ec_token(header, claims());
+        assert_invalid(validator.validate(&token), "critical");
+    }
+
+    #[test]
+    fn rejects_none_and_hmac() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+
+        let token = format!("{}.", message(&json!({ "alg": "none" }), &claims()));
+        assert_invalid(validator.validate(&token), "unsupported algorithm none");
+
+        // HMAC keyed with the public key, a classic confusion attack
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, keys.ec.public_key().as_ref());
+        let message = message(&json!({ "alg": "HS256", "kid": "ec" }), &claims());
+        let tag = ring::hmac::sign(&key, message.as_bytes());
+        let token = format!("{message}.{}", URL_SAFE_NO_PAD.encode(tag));
+        assert_invalid(validator.validate(&token), "unsupported algorithm HS256");
+    }
+
+    #[test]
+    fn rejects_unknown_kid_and_wrong_signatures() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+
+        let token = keys.ec_token(json!({ "alg": "ES256", "kid": "other" }), claims());
+        assert_invalid(validator.validate(&token), "signature");
+
+        // signed by another key under the same kid
+        let other = Keys::new();
+        let token = other.ec_token(json!({ "alg": "ES256", "kid": "ec" }), claims());
+        assert_invalid(validator.validate(&token), "signature");
+
+        // claims swapped after signing
+        let token = keys.ec_token(json!({ "alg": "ES256", "kid": "ec" }), claims());
+        let mut parts: Vec<_> = token.split('.').map(str::to_string).collect();
+        let mut tampered = claims();
+        tampered["sub"] = json!("mallory");
+        parts[1] = URL_SAFE_NO_PAD.encode(tampered.to_string());
+        assert_invalid(validator.validate(&parts.join(".")), "signature");
+
+        assert_invalid(validator.validate("not-a-jwt"), "three");
+    }
+
+    #[test]
+    fn checks_the_claims() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+        let token = |change: fn(&mut Value)| {
+            let mut claims = claims();
+            change(&mut claims);
+            keys.ec_token(json!({ "alg": "ES256" }), claims)
+        };
+
+        let expired = token(|claims| claims["exp"] = json!(now() - 10));
+        assert!(matches!(
+            validator.validate(&expired),
+            Err(AuthError::TokenExpired)
+        ));
+        let no_exp = token(|claims| {
+            claims.as_object_mut().unwrap().remove("exp");
+        });
+        assert_invalid(validator.validate(&no_exp), "exp");
+        let not_yet = token(|claims| claims["nbf"] = json!(now() + 60));
+        assert_invalid(validator.validate(&not_yet), "not valid yet");
+        let past_nbf = token(|claims| claims["nbf"] = json!(now() - 60));
+        assert!(validator.validate(&past_nbf).is_ok());
+        let wrong_issuer = token(|claims| claims["iss"] = json!("other"));
+        assert_invalid(validator.validate(&wrong_issuer), "issuer");
+        let wrong_audience = token(|claims| claims["aud"] = json!("other"));
+        assert_invalid(validator.validate(&wrong_audience), "audience");
+        let audiences = token(|claims| claims["aud"] = json!(["other", "api"]));
+        assert!(validator.validate(&audiences).is_ok());
+    }
+
+    #[test]
+    fn accepts_fractional_numeric_dates() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+        let token = |exp: Value, nbf: Value| {
+            let mut claims = claims();
+            claims["exp"] = exp;
+            claims["nbf"] = nbf;
+            keys.ec_token(json!({ "alg": "ES256" }), claims)
+        };
+        let now = now() as f64;
+
+        let valid = token(json!(now + 300.5), json!(now - 60.5));
+        assert!(validator.validate(&valid).is_ok());
+        let not_yet = token(json!(now + 300.5), json!(now + 60.5));
+        assert_invalid(validator.validate(&not_yet), "not valid yet");
+        let expired = token(json!(now - 10.5), json!(now - 60.5));
+        assert!(matches!(
+            validator.validate(&expired),
+            Err(AuthError::TokenExpired)
+        ));
+    }
+
+    #[test]
+    fn rejects_non_numeric_dates() {
+        let keys = Keys::new();
+        let validator = validator(vec![keys.ec_jwk("ec")]);
+        let token = |name: &str, value: Value| {
+            let mut claims = claims();
+            claims[name] = value;
+            keys.ec_token(json!({ "alg": "ES256" }), claims)
+        };
+
+        let exp = token("exp", json!((now() + 300).to_string()));
+        assert_invalid(validator.validate(&exp), "exp claim is not a number");
+        let nbf = token("nbf", json!("yesterday"));
+        assert_invalid(validator.validate(&nbf), "nbf claim is not a number");
+        let null = token("nbf", Value::Null);
+        assert_invalid(validator.validate(&null), "nbf claim is not a number");
+    }
+
+    #[test]
+    fn leeway_applies_to_exp_and_nbf() {
+        let keys = Keys::new();
+        let mut validator = validator(vec![keys.ec_jwk("ec")]);
+        validator.leeway = Duration::from_secs(60);
+        let mut claims = claims();
+        claims["exp"] = json!(now() - 30);
+        claims["nbf"] = json!(now() + 30);
+        let token = keys.ec_token(json!({ "alg": "ES256" }), claims);
+        assert!(validator.validate(&token).is_ok());
+    }
+}
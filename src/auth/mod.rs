@@ -0,0 +1,298 @@
+pub mod error;
+mod jwt;
+
+pub use jwt::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::AuthConfig;
+use crate::ApiError;
+use error::AuthError;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Roles are ordered, an admin can do whatever a reader can, and a reader whatever a
+/// metrics scraper can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // metrics
+    Metrics,
+    // queries
+    Reader,
+    // exports and refreshes
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Metrics => write!(f, "metrics"),
+            Role::Reader => write!(f, "reader"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Caller of an authenticated request, found in the request extensions.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    // name of the API key or subject of the JWT
+    pub name: String,
+    // None when the JWT has no known role
+    pub role: Option<Role>,
+}
+
+/// Checks the API keys and the bearer JWTs of the requests.
+pub struct Authenticator {
+    enabled: bool,
+    // by SHA-256 hash of the key, the keys themselves are never stored
+    api_keys: HashMap<[u8; 32], Principal>,
+    jwt: Option<JwtValidator>,
+    role_claim: String,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self, AuthError> {
+        let mut api_keys = HashMap::new();
+        for api_key in &config.api_keys {
+            let hash = hex::decode(&api_key.sha256)
+                .ok()
+                .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+                .ok_or_else(|| AuthError::InvalidApiKeyHash(api_key.name.clone()))?;
+            let principal = Principal {
+                name: api_key.name.clone(),
+                role: Some(api_key.role),
+            };
+            api_keys.insert(hash, principal);
+        }
+        let jwt = match &config.jwks_file {
+            Some(jwks_file) => Some(JwtValidator::new(jwks_file, config)?),
+            None => None,
+        };
+        Ok(Self {
+            enabled: config.enabled,
+            api_keys,
+            jwt,
+            role_claim: config.role_claim.clone(),
+        })
+    }
+
+    /// Bearer tokens with three dot separated parts are JWTs, the others API keys.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, AuthError> {
+        if let Some(api_key) = headers.get(API_KEY_HEADER) {
+            let api_key = api_key.to_str().map_err(|_| AuthError::UnknownApiKey)?;
+            return self.authenticate_api_key(api_key);
+        }
+        let Some(authorization) = headers.get(AUTHORIZATION) else {
+            return Err(AuthError::MissingCredentials);
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or(AuthError::MalformedHeader)?;
+        match token.split('.').count() {
+            3 => self.authenticate_jwt(token),
+            _ => self.authenticate_api_key(token),
+        }
+    }
+
+    fn authenticate_api_key(&self, api_key: &str) -> Result<Principal, AuthError> {
+        let hash: [u8; 32] = Sha256::digest(api_key.as_bytes()).into();
+        self.api_keys
+            .get(&hash)
+            .cloned()
+            .ok_or(AuthError::UnknownApiKey)
+    }
+
+    fn authenticate_jwt(&self, token: &str) -> Result<Principal, AuthError> {
+        let jwt = self.jwt.as_ref().ok_or(AuthError::JwtNotAccepted)?;
+        let claims = jwt.validate(token)?;
+        let name = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown")
+            .to_string();
+        Ok(Principal {
+            name,
+            role: self.role(&claims),
+        })
+    }
+
+    // the highest known role of the claim, a string or an array of strings
+    fn role(&self, claims: &Map<String, Value>) -> Option<Role> {
+        let roles = match claims.get(&self.role_claim)? {
+            Value::Array(roles) => roles.clone(),
+            role => vec![role.clone()],
+        };
+        roles
+            .into_iter()
+            .filter_map(|role| serde_json::from_value::<Role>(role).ok())
+            .max()
+    }
+}
+
+/// State of the `authorize` middleware, the role required by the routes it guards.
+#[derive(Clone)]
+pub struct RequireRole {
+    authenticator: Arc<Authenticator>,
+    role: Role,
+}
+
+impl RequireRole {
+    pub fn new(authenticator: Arc<Authenticator>, role: Role) -> Self {
+        Self {
+            authenticator,
+            role,
+        }
+    }
+}
+
+/// Rejects the requests without valid credentials with 401, and those of callers
+/// lacking the role with 403.
+pub async fn authorize(
+    State(require): State<RequireRole>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if !require.authenticator.enabled {
+        return Ok(next.run(request).await);
+    }
+    let principal = require
+        .authenticator
+        .authenticate(request.headers())
+        .map_err(ApiError::Unauthorized)?;
+    if principal.role.is_none_or(|role| role < require.role) {
+        tracing::warn!(principal = principal.name, role = ?principal.role, "forbidden");
+        return Err(ApiError::Forbidden(require.role));
+    }
+    tracing::debug!(principal = principal.name, role = ?principal.role, "authenticated");
+    request.extensions_mut().insert(principal);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::ApiKeyConfig;
+
+    fn authenticator() -> Authenticator {
+        let api_key = |name: &str, key: &str, role| ApiKeyConfig {
+            name: name.to_string(),
+            sha256: hex::encode(Sha256::digest(key)),
+            role,
+        };
+        let config = AuthConfig {
+            api_keys: vec![
+                api_key("prometheus", "metrics-key", Role::Metrics),
+                api_key("dashboards", "reader-key", Role::Reader),
+                api_key("ops", "admin-key", Role::Admin),
+            ],
+            ..Default::default()
+        };
+        Authenticator::new(&config).unwrap()
+    }
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn roles_are_ordered() {
+        assert!(Role::Metrics < Role::Reader);
+        assert!(Role::Reader < Role::Admin);
+        let role: Role = serde_json::from_value(json!("metrics")).unwrap();
+        assert_eq!(role, Role::Metrics);
+        assert_eq!(Role::Admin.to_string(), "admin");
+    }
+
+    #[test]
+    fn api_keys_are_read_from_both_headers() {
+        let authenticator = authenticator();
+        let principal = authenticator
+            .authenticate(&headers(API_KEY_HEADER, "reader-key"))
+            .unwrap();
+        assert_eq!(principal.name, "dashboards");
+        assert_eq!(principal.role, Some(Role::Reader));
+
+        let principal = authenticator
+            .authenticate(&headers("authorization", "Bearer admin-key"))
+            .unwrap();
+        assert_eq!(principal.name, "ops");
+        assert_eq!(principal.role, Some(Role::Admin));
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_credentials() {
+        let authenticator = authenticator();
+        assert!(matches!(
+            authenticator.authenticate(&HeaderMap::new()),
+            Err(AuthError::MissingCredentials)
+        ));
+        assert!(matches!(
+            authenticator.authenticate(&headers(API_KEY_HEADER, "other-key")),
+            Err(AuthError::UnknownApiKey)
+        ));
+        assert!(matches!(
+            authenticator.authenticate(&headers("authorization", "Basic cmVhZGVy")),
+            Err(AuthError::MalformedHeader)
+        ));
+        // JWTs are rejected without a JWKS
+        assert!(matches!(
+            authenticator.authenticate(&headers("authorization", "Bearer a.b.c")),
+            Err(AuthError::JwtNotAccepted)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_api_key_hashes() {
+        let config = AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                name: "broken".to_string(),
+                sha256: "not hex".to_string(),
+                role: Role::Reader,
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            Authenticator::new(&config),
+            Err(AuthError::InvalidApiKeyHash(name)) if name == "broken"
+        ));
+    }
+
+    #[test]
+    fn role_claim_keeps_the_highest_known_role() {
+        let authenticator = authenticator();
+        let role = |claims: Value| authenticator.role(claims.as_object().unwrap());
+        assert_eq!(role(json!({ "role": "reader" })), Some(Role::Reader));
+        assert_eq!(
+            role(json!({ "role": ["metrics", "admin", "reader"] })),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            role(json!({ "role": ["owner", "metrics"] })),
+            Some(Role::Metrics)
+        );
+        assert_eq!(role(json!({ "role": "owner" })), None);
+        assert_eq!(role(json!({ "roles": "admin" })), None);
+    }
+}
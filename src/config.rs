@@ -0,0 +1,552 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::auth::Role;
+use crate::logging_table::{IngestionOptions, TableMode};
+use crate::utils::constants::env::{
+    CONFIG_ENV_VAR_PREFIX, CONFIG_FILE_ENV_VAR, LOG_GROUP_NAME_ENV_VAR, LOG_SOURCE_FIXTURE_ENV_VAR,
+};
+
+/// Command line flags, they take precedence over the environment and the config file.
+#[derive(Debug, Default, Parser)]
+#[command(version, about = "SQL queries over CloudWatch log groups")]
+pub struct Cli {
+    /// TOML or YAML configuration file
+    #[arg(short, long, env = CONFIG_FILE_ENV_VAR)]
+    pub config: Option<PathBuf>,
+
+    /// Address the server listens on, e.g. 0.0.0.0:8080
+    #[arg(long)]
+    pub app_address: Option<String>,
+
+    /// Log groups to serve, comma separated
+    #[arg(long = "log-group", value_delimiter = ',')]
+    pub log_group_names: Vec<String>,
+
+    /// MemoryLogSource fixture file served instead of CloudWatch
+    #[arg(long)]
+    pub log_source_fixture: Option<PathBuf>,
+
+    /// Overrides any setting by its dotted path, e.g. --set query.max_rows=500
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+/// Runtime configuration, every setting but `log_group_names` has a default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub app_address: String,
+    pub log_group_names: Vec<String>,
+    // when set logs are served from this MemoryLogSource fixture instead of CloudWatch
+    pub log_source_fixture: Option<PathBuf>,
+    // time given to in-flight requests to complete once a shutdown starts
+    pub shutdown_grace_period_secs: u64,
+    pub aws: AwsConfig,
+    pub table: TableConfig,
+    pub ingestion: IngestionConfig,
+    pub query: QueryConfig,
+    pub export: ExportConfig,
+    pub auth: AuthConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AwsConfig {
+    pub region: String,
+    pub max_retries: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TableConfig {
+    pub name: String,
+    pub mode: TableMode,
+    // snapshot mode only, 0 disables the periodic refresh
+    pub refresh_interval_secs: u64,
+    // Parquet cache of the ingested events, snapshot mode only
+    pub cache: bool,
+    pub cache_dir: PathBuf,
+    // bounds the in-memory table used without cache, the newest events are kept
+    pub max_rows: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestionConfig {
+    pub max_log_streams: Option<usize>,
+    pub log_streams_order_by: LogStreamsOrderBy,
+    pub log_streams_descending: bool,
+    pub max_events_per_stream: Option<usize>,
+    pub max_bytes_per_stream: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStreamsOrderBy {
+    LogStreamName,
+    LastEventTime,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    // page_size upper bound
+    pub max_rows: usize,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    pub dir: PathBuf,
+    pub row_group_size: usize,
+    pub max_rows_per_file: usize,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // on by default, when disabled every route is open, e.g. behind an authenticating proxy
+    pub enabled: bool,
+    pub api_keys: Vec<ApiKeyConfig>,
+    // local JSON Web Key Set checking the bearer JWTs, JWTs are rejected without it
+    pub jwks_file: Option<PathBuf>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    // JWT claim holding the role, a string or an array of strings
+    pub role_claim: String,
+    // clock skew tolerated on exp and nbf
+    pub leeway_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    // logged instead of the key
+    pub name: String,
+    // hex encoded SHA-256 of the key, e.g. from `printf %s "$KEY" | sha256sum`
+    pub sha256: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read the config file {0}")]
+    Read(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to parse the config file {0}: {1}")]
+    Parse(PathBuf, String),
+
+    #[error("Unsupported config file {0}, expected a .toml, .yaml or .yml file")]
+    UnsupportedFile(PathBuf),
+
+    #[error("Invalid override {0}, expected KEY=VALUE")]
+    InvalidOverride(String),
+
+    #[error("Invalid setting {0}: {1}")]
+    InvalidSetting(String, String),
+
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            app_address: "0.0.0.0:8080".to_string(),
+            log_group_names: vec![],
+            log_source_fixture: None,
+            shutdown_grace_period_secs: 30,
+            aws: AwsConfig::default(),
+            table: TableConfig::default(),
+            ingestion: IngestionConfig::default(),
+            query: QueryConfig::default(),
+            export: ExportConfig::default(),
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        Self {
+            region: "eu-central-1".to_string(),
+            max_retries: 10,
+        }
+    }
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            name: "logs".to_string(),
+            mode: TableMode::Snapshot,
+            refresh_interval_secs: 300,
+            cache: true,
+            cache_dir: PathBuf::from("cache"),
+            max_rows: None,
+        }
+    }
+}
+
+impl Default for IngestionConfig {
+    fn default() -> Self {
+        Self {
+            max_log_streams: None,
+            log_streams_order_by: LogStreamsOrderBy::LastEventTime,
+            log_streams_descending: true,
+            max_events_per_stream: None,
+            max_bytes_per_stream: None,
+        }
+    }
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            max_rows: 1000,
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("exports"),
+            row_group_size: 1024 * 1024,
+            max_rows_per_file: 10_000_000,
+            timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            api_keys: vec![],
+            jwks_file: None,
+            jwt_issuer: None,
+            jwt_audience: None,
+            role_claim: "role".to_string(),
+            leeway_secs: 60,
+        }
+    }
+}
+
+impl Config {
+    /// Layers the config file, the environment and the command line flags, in that order.
+    ///
+    /// Besides `LOG_GROUP_NAME` and `LOG_SOURCE_FIXTURE`, any setting can be set from the
+    /// environment as `CLOUDWATCH_VIEWER_<PATH>` with `__` separating the sections,
+    /// e.g. `CLOUDWATCH_VIEWER_QUERY__MAX_ROWS=500`.
+    pub fn load(cli: Cli) -> Result<Self, ConfigError> {
+        let mut settings = match &cli.config {
+            Some(file_path) => read_file(file_path)?,
+            None => Value::Object(Map::new()),
+        };
+
+        for (key, value) in env::vars() {
+            if let Some(path) = key.strip_prefix(CONFIG_ENV_VAR_PREFIX) {
+                let path = path.to_lowercase().replace("__", ".");
+                set(&mut settings, &path, parse_value(&value));
+            }
+        }
+        if let Ok(secret) = env::var(LOG_GROUP_NAME_ENV_VAR) {
+            set(&mut settings, "log_group_names", split_list(&secret));
+        }
+        if let Ok(file_path) = env::var(LOG_SOURCE_FIXTURE_ENV_VAR) {
+            if !file_path.is_empty() {
+                set(
+                    &mut settings,
+                    "log_source_fixture",
+                    Value::String(file_path),
+                );
+            }
+        }
+
+        for item in &cli.overrides {
+            let (path, value) = item
+                .split_once('=')
+                .ok_or_else(|| ConfigError::InvalidOverride(item.clone()))?;
+            set(&mut settings, path.trim(), parse_value(value));
+        }
+        if let Some(app_address) = cli.app_address {
+            set(&mut settings, "app_address", Value::String(app_address));
+        }
+        if !cli.log_group_names.is_empty() {
+            set(
+                &mut settings,
+                "log_group_names",
+                split_list(&cli.log_group_names.join(",")),
+            );
+        }
+        if let Some(file_path) = cli.log_source_fixture {
+            set(
+                &mut settings,
+                "log_source_fixture",
+                Value::String(file_path.to_string_lossy().into_owned()),
+            );
+        }
+
+        let config: Self = serde_path_to_error::deserialize(settings).map_err(|e| {
+            ConfigError::InvalidSetting(e.path().to_string(), e.inner().to_string())
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reports every invalid setting at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = vec![];
+        let port = self
+            .app_address
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse::<u16>().ok());
+        if port.is_none() {
+            errors.push(format!(
+                "app_address must be a host and a port, got {:?}",
+                self.app_address
+            ));
+        }
+        if self.log_group_names.is_empty() {
+            errors.push(format!(
+                "log_group_names must not be empty, set it in the config file, {LOG_GROUP_NAME_ENV_VAR} or --log-group"
+            ));
+        }
+        if let Some(file_path) = &self.log_source_fixture {
+            if !file_path.is_file() {
+                errors.push(format!(
+                    "log_source_fixture {} is not a file",
+                    file_path.display()
+                ));
+            }
+        }
+        if self.table.name.is_empty() {
+            errors.push("table.name must not be empty".to_string());
+        }
+        for (name, value) in [
+            ("table.max_rows", self.table.max_rows),
+            ("ingestion.max_log_streams", self.ingestion.max_log_streams),
+            (
+                "ingestion.max_events_per_stream",
+                self.ingestion.max_events_per_stream,
+            ),
+            (
+                "ingestion.max_bytes_per_stream",
+                self.ingestion.max_bytes_per_stream,
+            ),
+            ("query.max_rows", Some(self.query.max_rows)),
+            ("export.row_group_size", Some(self.export.row_group_size)),
+            (
+                "export.max_rows_per_file",
+                Some(self.export.max_rows_per_file),
+            ),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{name} must be positive"));
+            }
+        }
+        if self.aws.max_retries < 1 {
+            errors.push("aws.max_retries must be positive".to_string());
+        }
+        for (name, value) in [
+            ("query.timeout_secs", self.query.timeout_secs),
+            ("export.timeout_secs", self.export.timeout_secs),
+        ] {
+            if value == 0 {
+                errors.push(format!("{name} must be positive"));
+            }
+        }
+        if self.auth.enabled && self.auth.api_keys.is_empty() && self.auth.jwks_file.is_none() {
+            errors.push(
+                "auth.enabled requires auth.api_keys or auth.jwks_file, set auth.enabled = false to open every route"
+                    .to_string(),
+            );
+        }
+        for api_key in &self.auth.api_keys {
+            let hash = &api_key.sha256;
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                errors.push(format!(
+                    "auth.api_keys {} must have a hex encoded SHA-256 hash",
+                    api_key.name
+                ));
+            }
+        }
+        if let Some(file_path) = &self.auth.jwks_file {
+            if !file_path.is_file() {
+                errors.push(format!(
+                    "auth.jwks_file {} is not a file",
+                    file_path.display()
+                ));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(errors)),
+        }
+    }
+
+    /// Defaults for tests: an ephemeral local port, no cache and no authentication.
+    pub fn test(log_group_names: Vec<String>) -> Self {
+        let mut config = Self {
+            app_address: "127.0.0.1:0".to_string(),
+            log_group_names,
+            ..Self::default()
+        };
+        config.table.cache = false;
+        config.auth.enabled = false;
+        config
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_period_secs)
+    }
+}
+
+impl TableConfig {
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        (self.refresh_interval_secs > 0).then(|| Duration::from_secs(self.refresh_interval_secs))
+    }
+
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache.then_some(self.cache_dir.as_path())
+    }
+}
+
+impl From<&IngestionConfig> for IngestionOptions {
+    fn from(config: &IngestionConfig) -> Self {
+        Self::new(
+            config.max_log_streams,
+            config.log_streams_order_by.into(),
+            config.log_streams_descending,
+            config.max_events_per_stream,
+            config.max_bytes_per_stream,
+        )
+    }
+}
+
+impl From<LogStreamsOrderBy> for OrderBy {
+    fn from(order_by: LogStreamsOrderBy) -> Self {
+        match order_by {
+            LogStreamsOrderBy::LogStreamName => Self::LogStreamName,
+            LogStreamsOrderBy::LastEventTime => Self::LastEventTime,
+        }
+    }
+}
+
+fn read_file(file_path: &Path) -> Result<Value, ConfigError> {
+    let content = std::fs::read_to_string(file_path)
+        .map_err(|e| ConfigError::Read(file_path.to_path_buf(), e))?;
+    let parse_error = |message: String| ConfigError::Parse(file_path.to_path_buf(), message);
+    let settings = match file_path.extension().and_then(|x| x.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|e| parse_error(e.to_string()))?,
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+        }
+        _ => return Err(ConfigError::UnsupportedFile(file_path.to_path_buf())),
+    };
+    match settings {
+        // an empty YAML file is null
+        Value::Null => Ok(Value::Object(Map::new())),
+        settings => Ok(settings),
+    }
+}
+
+// environment and command line values are JSON when they parse as such, strings otherwise
+fn parse_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+fn split_list(value: &str) -> Value {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| Value::String(x.to_string()))
+        .collect()
+}
+
+// sets the value at a dotted path, creating the missing sections
+fn set(settings: &mut Value, path: &str, value: Value) {
+    let mut current = settings;
+    for key in path.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(key)
+            .or_insert(Value::Null);
+    }
+    *current = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => vec![],
+            Err(ConfigError::Invalid(errors)) => errors,
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn test_config_is_valid() {
+        assert_eq!(
+            errors(&Config::test(vec!["group".to_string()])),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn auth_is_required_by_default() {
+        let mut config = Config::test(vec!["group".to_string()]);
+        config.auth = AuthConfig::default();
+        let errors = errors(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("auth.enabled requires"), "{errors:?}");
+
+        config.auth.api_keys.push(ApiKeyConfig {
+            name: "dashboards".to_string(),
+            sha256: "ab".repeat(32),
+            role: Role::Reader,
+        });
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported() {
+        let mut config = Config::test(vec![]);
+        config.aws.max_retries = 0;
+        config.query.max_rows = 0;
+        config.table.max_rows = Some(0);
+        let errors = errors(&config);
+        for expected in [
+            "log_group_names must not be empty",
+            "aws.max_retries must be positive",
+            "query.max_rows must be positive",
+            "table.max_rows must be positive",
+        ] {
+            assert!(
+                errors.iter().any(|x| x.starts_with(expected)),
+                "{expected}: {errors:?}"
+            );
+        }
+    }
+}
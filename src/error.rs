@@ -1,43 +1,456 @@
+use std::time::Duration;
+
 use axum::{
-    http::StatusCode,
+    http::{header::WWW_AUTHENTICATE, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use color_eyre::eyre::Report;
+use datafusion::{
+    arrow::error::ArrowError, error::DataFusionError, sql::sqlparser::parser::ParserError,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::utils::tracing::log_error_chain;
+use crate::auth::{error::AuthError, Role};
+use crate::logging_table::error::{LoggingTableError, QueryValidationError};
+use crate::utils::metrics::metrics;
+use crate::utils::tracing::{current_request_id, log_error_chain};
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Incorrect query")]
     IncorrectQuery,
 
-    #[error("Query result is empty")]
-    QueryResultIsEmpty,
+    #[error("Invalid query: {0}")]
+    InvalidQuery(#[source] QueryValidationError),
+
+    #[error("SQL parse error: {message}")]
+    SqlParse {
+        message: String,
+        position: Option<SqlPosition>,
+    },
+
+    // unknown table or column, type mismatch, unsupported SQL
+    #[error("Planning error: {message}")]
+    Plan {
+        message: String,
+        position: Option<SqlPosition>,
+    },
+
+    // a failed cast, a division by zero or an overflow, other execution errors are unexpected
+    #[error("Execution error: {0}")]
+    Execution(String),
+
+    #[error("Resources exhausted: {0}")]
+    ResourcesExhausted(String),
+
+    #[error("Invalid cursor")]
+    InvalidCursor,
+
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Invalid export options: {0}")]
+    InvalidExportOptions(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(#[source] AuthError),
+
+    #[error("Forbidden, the {0} role is required")]
+    Forbidden(Role),
+
+    // lazy tables are fetched at query time
+    #[error("Table {0} is not refreshable")]
+    NotRefreshable(String),
+
+    #[error("Query timed out after {elapsed:?}")]
+    QueryTimeout {
+        elapsed: Duration,
+        // the per request timeout fired rather than the server one
+        client_timeout: bool,
+    },
 
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
 
+/// Position in the SQL text, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqlPosition {
+    pub line: u64,
+    pub column: u64,
+}
+
+impl SqlPosition {
+    // sqlparser appends " at Line: 1, Column: 8" to the errors it can locate
+    fn find(message: &str) -> Option<Self> {
+        let (_, position) = message.rsplit_once("Line: ")?;
+        let (line, column) = position.split_once(", Column: ")?;
+        let column_end = column
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(column.len());
+        Some(Self {
+            line: line.parse().ok()?,
+            column: column[..column_end].parse().ok()?,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
+    // stable, machine readable, see ApiError::code
+    pub code: String,
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<SqlPosition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<u128>,
+    // matches the x-request-id response header and the request_id of the server logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::IncorrectQuery => "incorrect_query",
+            ApiError::InvalidQuery(_) => "invalid_query",
+            ApiError::SqlParse { .. } => "sql_parse_error",
+            ApiError::Plan { .. } => "plan_error",
+            ApiError::Execution(_) => "execution_error",
+            ApiError::ResourcesExhausted(_) => "resources_exhausted",
+            ApiError::InvalidCursor => "invalid_cursor",
+            ApiError::NotAcceptable(_) => "not_acceptable",
+            ApiError::UnsupportedFormat(_) => "unsupported_format",
+            ApiError::InvalidExportOptions(_) => "invalid_export_options",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotRefreshable(_) => "not_refreshable",
+            ApiError::QueryTimeout { .. } => "query_timeout",
+            ApiError::UnexpectedError(_) => "internal_error",
+        }
+    }
+
+    fn sql_parse(e: &ParserError) -> Self {
+        let message = match e {
+            ParserError::TokenizerError(message) | ParserError::ParserError(message) => {
+                message.clone()
+            }
+            ParserError::RecursionLimitExceeded => e.to_string(),
+        };
+        ApiError::SqlParse {
+            position: SqlPosition::find(&message),
+            message,
+        }
+    }
+
+    fn plan(message: String) -> Self {
+        ApiError::Plan {
+            position: SqlPosition::find(&message),
+            message,
+        }
+    }
+}
+
+impl From<QueryValidationError> for ApiError {
+    fn from(e: QueryValidationError) -> Self {
+        match e {
+            QueryValidationError::ParseError(e) => ApiError::sql_parse(&e),
+            QueryValidationError::DataFusionError(e) => e.into(),
+            QueryValidationError::UnknownTable(_) => ApiError::plan(e.to_string()),
+            e => ApiError::InvalidQuery(e),
+        }
+    }
+}
+
+// the errors caused by the query are told apart from the server failures, errors that
+// are not known to be caused by the query are unexpected
+impl From<DataFusionError> for ApiError {
+    fn from(e: DataFusionError) -> Self {
+        // Context wrappers and errors raised inside streams are unwrapped
+        let root = e.find_root();
+        match root {
+            DataFusionError::SQL(e, _) => ApiError::sql_parse(e),
+            DataFusionError::Plan(_)
+            | DataFusionError::SchemaError(..)
+            | DataFusionError::NotImplemented(_) => ApiError::plan(root.strip_backtrace()),
+            DataFusionError::ResourcesExhausted(_)
+            | DataFusionError::ArrowError(ArrowError::MemoryError(_), _) => {
+                ApiError::ResourcesExhausted(root.strip_backtrace())
+            }
+            root if is_query_execution_error(root) => ApiError::Execution(root.strip_backtrace()),
+            _ => ApiError::UnexpectedError(e.into()),
+        }
+    }
+}
+
+// failed casts, divisions by zero and overflows, raised by arrow kernels or by DataFusion
+fn is_query_execution_error(e: &DataFusionError) -> bool {
+    match e {
+        DataFusionError::ArrowError(e, _) => is_query_arrow_error(e),
+        DataFusionError::External(e) => e
+            .downcast_ref::<ArrowError>()
+            .is_some_and(is_query_arrow_error),
+        DataFusionError::Execution(message) => is_query_error_message(message),
+        _ => false,
+    }
+}
+
+fn is_query_arrow_error(e: &ArrowError) -> bool {
+    match e {
+        ArrowError::DivideByZero | ArrowError::CastError(_) | ArrowError::ArithmeticOverflow(_) => {
+            true
+        }
+        ArrowError::ComputeError(message) => is_query_error_message(message),
+        _ => false,
+    }
+}
+
+fn is_query_error_message(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    [
+        "divide by zero",
+        "division by zero",
+        "overflow",
+        "cast error",
+        "cannot cast",
+        "can't cast",
+    ]
+    .iter()
+    .any(|x| message.contains(x))
+}
+
+impl From<LoggingTableError> for ApiError {
+    fn from(e: LoggingTableError) -> Self {
+        match e {
+            LoggingTableError::DataFusionError(e) => e.into(),
+            e => ApiError::UnexpectedError(e.into()),
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
 
+        let code = self.code().to_string();
+        if let ApiError::SqlParse { .. }
+        | ApiError::Plan { .. }
+        | ApiError::Execution(_)
+        | ApiError::ResourcesExhausted(_) = self
+        {
+            metrics().datafusion_errors.inc(&[&code]);
+        }
+        let elapsed_ms = match &self {
+            ApiError::QueryTimeout { elapsed, .. } => Some(elapsed.as_millis()),
+            _ => None,
+        };
+        let position = match &self {
+            ApiError::SqlParse { position, .. } | ApiError::Plan { position, .. } => *position,
+            _ => None,
+        };
         let (status, error_message) = match self {
-            ApiError::IncorrectQuery => (StatusCode::BAD_REQUEST, "Incorrect query"),
-            ApiError::QueryResultIsEmpty => (StatusCode::NOT_FOUND, "Not found"),
-            ApiError::UnexpectedError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error"),
+            ApiError::IncorrectQuery => (StatusCode::BAD_REQUEST, "Incorrect query".to_string()),
+            ApiError::InvalidQuery(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+            ApiError::SqlParse { message, .. } => (StatusCode::BAD_REQUEST, message),
+            ApiError::Plan { message, .. } => (StatusCode::BAD_REQUEST, message),
+            ApiError::Execution(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::ResourcesExhausted(message) => (StatusCode::SERVICE_UNAVAILABLE, message),
+            ApiError::InvalidCursor => (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()),
+            ApiError::NotAcceptable(accept) => (
+                StatusCode::NOT_ACCEPTABLE,
+                format!("No supported media type in {accept}"),
+            ),
+            ApiError::UnsupportedFormat(e) => (StatusCode::BAD_REQUEST, e),
+            ApiError::InvalidExportOptions(e) => (StatusCode::BAD_REQUEST, e),
+            ApiError::Unauthorized(e) => (StatusCode::UNAUTHORIZED, e.to_string()),
+            ApiError::Forbidden(role) => (
+                StatusCode::FORBIDDEN,
+                format!("The {role} role is required"),
+            ),
+            ApiError::NotRefreshable(table_name) => (
+                StatusCode::BAD_REQUEST,
+                format!("Table {table_name} is fetched at query time and not refreshable"),
+            ),
+            ApiError::QueryTimeout {
+                elapsed,
+                client_timeout,
+            } => {
+                let status = match client_timeout {
+                    true => StatusCode::REQUEST_TIMEOUT,
+                    false => StatusCode::GATEWAY_TIMEOUT,
+                };
+                (
+                    status,
+                    format!("Query timed out after {} ms", elapsed.as_millis()),
+                )
+            }
+            ApiError::UnexpectedError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unexpected error".to_string(),
+            ),
         };
         let body = Json(ErrorResponse {
-            error: error_message.to_string(),
+            code,
+            error: error_message,
+            position,
+            elapsed_ms,
+            request_id: current_request_id(),
         });
-        (status, body).into_response()
+        match status {
+            StatusCode::UNAUTHORIZED => {
+                (status, [(WWW_AUTHENTICATE, "Bearer")], body).into_response()
+            }
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrow(e: ArrowError) -> DataFusionError {
+        DataFusionError::ArrowError(e, None)
+    }
+
+    fn context(e: DataFusionError) -> DataFusionError {
+        e.context("while executing the query")
+    }
+
+    #[test]
+    fn datafusion_errors_are_classified() {
+        let io_error = || std::io::Error::other("disk failure");
+        let cases = [
+            (
+                DataFusionError::SQL(ParserError::ParserError("Expected: ...".into()), None),
+                "sql_parse_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                DataFusionError::Plan("No field named x".into()),
+                "plan_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                DataFusionError::NotImplemented("Unsupported SQL".into()),
+                "plan_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                context(DataFusionError::Plan("No field named x".into())),
+                "plan_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                arrow(ArrowError::DivideByZero),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                arrow(ArrowError::CastError(
+                    "Cannot cast string 'a' to Int64".into(),
+                )),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                arrow(ArrowError::ArithmeticOverflow(
+                    "Overflow happened on: 1 + 2".into(),
+                )),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                arrow(ArrowError::ComputeError(
+                    "Overflow happened on: 1 * 2".into(),
+                )),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                DataFusionError::Execution("Divide by zero error".into()),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                context(DataFusionError::Execution("Cast error: Cannot cast".into())),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                DataFusionError::External(Box::new(ArrowError::DivideByZero)),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            // raised inside a stream, wrapped by arrow
+            (
+                arrow(ArrowError::ExternalError(Box::new(arrow(
+                    ArrowError::DivideByZero,
+                )))),
+                "execution_error",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                DataFusionError::ResourcesExhausted("Failed to allocate".into()),
+                "resources_exhausted",
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                arrow(ArrowError::MemoryError("Failed to allocate".into())),
+                "resources_exhausted",
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                DataFusionError::Execution("Failed to read the cache file".into()),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                context(DataFusionError::Execution("Task join error".into())),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                arrow(ArrowError::ComputeError("Invalid offsets".into())),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                arrow(ArrowError::InvalidArgumentError("column lengths".into())),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                arrow(ArrowError::IoError("disk failure".into(), io_error())),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                DataFusionError::IoError(io_error()),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                DataFusionError::Internal("unexpected plan".into()),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                DataFusionError::External(Box::new(io_error())),
+                "internal_error",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (e, code, status) in cases {
+            let description = e.to_string();
+            let e = ApiError::from(e);
+            assert_eq!(e.code(), code, "{description}");
+            assert_eq!(e.into_response().status(), status, "{description}");
+        }
     }
 }
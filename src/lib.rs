@@ -1,38 +1,105 @@
 pub mod app_state;
+pub mod auth;
+pub mod config;
 pub mod error;
+pub mod log_source;
 pub mod logging_table;
 pub mod routes;
 pub mod utils;
 
 use crate::app_state::AppState;
+use crate::auth::{authorize, Authenticator, RequireRole, Role};
+use crate::config::Config;
 use crate::routes::*;
 
 use axum::{
+    middleware,
     routing::{get, post},
     serve::Serve,
     Router,
 };
 use error::ApiError;
+use std::future::IntoFuture;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use utils::{
+    metrics::record_query_metrics,
+    shutdown::cancel_on_signal,
+    tracing::{make_span_with_request_id, on_request, on_response, scope_request_id},
+};
 
 pub struct Application {
     server: Serve<Router, Router>,
     pub address: String,
+    shutdown: CancellationToken,
+    grace_period: Duration,
 }
 
 impl Application {
-    fn new(server: Serve<Router, Router>, address: String) -> Self {
-        Self { server, address }
+    fn new(server: Serve<Router, Router>, address: String, grace_period: Duration) -> Self {
+        let shutdown = CancellationToken::new();
+        // signals are handled from the start so the initial ingestion can be interrupted too
+        tokio::spawn(cancel_on_signal(shutdown.clone()));
+        Self {
+            server,
+            address,
+            shutdown,
+            grace_period,
+        }
     }
 
-    pub async fn build(address: &str, app_state: AppState) -> Result<Self, ApiError> {
-        let router = Router::new()
+    pub async fn build(config: &Config, app_state: AppState) -> Result<Self, ApiError> {
+        let authenticator = Arc::new(
+            Authenticator::new(&config.auth).map_err(|e| ApiError::UnexpectedError(e.into()))?,
+        );
+        if !config.auth.enabled {
+            tracing::warn!("authentication is disabled, every route is open");
+        }
+        let scraper = RequireRole::new(authenticator.clone(), Role::Metrics);
+        let reader = RequireRole::new(authenticator.clone(), Role::Reader);
+        let admin = RequireRole::new(authenticator, Role::Admin);
+
+        // the probes stay open
+        let public = Router::new()
             .route("/", get(|| async { "CloudWatchViewer API" }))
             .route("/alive", get(ping))
-            .route("/query", post(post_query))
-            .with_state(app_state);
+            .route("/ready", get(get_ready));
+        let scrapers = Router::new()
+            .route("/metrics", get(get_metrics))
+            .route_layer(middleware::from_fn_with_state(scraper, authorize));
+        let readers = Router::new().route(
+            "/query",
+            post(post_query)
+                .layer(middleware::from_fn_with_state(reader, authorize))
+                .layer(middleware::from_fn(record_query_metrics)),
+        );
+        let admins = Router::new()
+            .route("/export", post(post_export))
+            .route("/refresh", post(post_refresh))
+            .route_layer(middleware::from_fn_with_state(admin, authorize));
+        let router = public
+            .merge(scrapers)
+            .merge(readers)
+            .merge(admins)
+            .with_state(app_state)
+            // the last layer added runs first
+            .layer(middleware::from_fn(scope_request_id))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(make_span_with_request_id)
+                    .on_request(on_request)
+                    .on_response(on_response),
+            )
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
-        let listener = TcpListener::bind(address)
+        let listener = TcpListener::bind(&config.app_address)
             .await
             .map_err(|e| ApiError::UnexpectedError(e.into()))?;
         let address = listener
@@ -40,14 +107,39 @@ impl Application {
             .map_err(|e| ApiError::UnexpectedError(e.into()))?
             .to_string();
         let server = axum::serve(listener, router);
-        Ok(Application::new(server, address))
+        Ok(Application::new(
+            server,
+            address,
+            config.shutdown_grace_period(),
+        ))
+    }
+
+    /// Cancelled on SIGTERM or SIGINT, cancelling it shuts the application down.
+    pub fn shutdown_handle(&self) -> CancellationToken {
+        self.shutdown.clone()
     }
 
+    /// Serves until a shutdown, then stops accepting connections and gives the in-flight
+    /// requests the grace period to complete.
     pub async fn run(self) -> Result<(), ApiError> {
         tracing::info!("listening on {}", &self.address);
-        self.server
-            .await
-            .map_err(|e| ApiError::UnexpectedError(e.into()))?;
+        let shutdown = self.shutdown.clone();
+        let server = self
+            .server
+            .with_graceful_shutdown(async move { shutdown.cancelled().await })
+            .into_future();
+        tokio::pin!(server);
+        tokio::select! {
+            biased;
+            _ = self.shutdown.cancelled() => {}
+            res = &mut server => return res.map_err(|e| ApiError::UnexpectedError(e.into())),
+        }
+
+        tracing::info!(grace_period = ?self.grace_period, "shutting down");
+        match tokio::time::timeout(self.grace_period, server).await {
+            Ok(res) => res.map_err(|e| ApiError::UnexpectedError(e.into()))?,
+            Err(_) => tracing::warn!("grace period elapsed, in-flight requests are dropped"),
+        }
         Ok(())
     }
 }
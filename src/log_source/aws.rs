@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use aws_sdk_cloudwatchlogs::{operation::get_log_events::GetLogEventsError, Client};
+use futures_util::{stream::BoxStream, StreamExt};
+
+use super::{
+    LogEvent, LogEventsPage, LogEventsRequest, LogSource, LogStreamInfo, LogStreamsRequest,
+};
+use crate::logging_table::error::LoggingTableError;
+
+#[derive(Clone)]
+pub struct AwsLogSource {
+    client: Client,
+}
+
+impl AwsLogSource {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LogSource for AwsLogSource {
+    fn describe_log_streams(
+        &self,
+        request: LogStreamsRequest,
+    ) -> BoxStream<'static, Result<Vec<LogStreamInfo>, LoggingTableError>> {
+        let pages = self
+            .client
+            .describe_log_streams()
+            .log_group_name(request.log_group_name)
+            .set_log_stream_name_prefix(request.log_stream_name_prefix)
+            .order_by(request.order_by)
+            .descending(request.descending)
+            .into_paginator()
+            .send();
+
+        futures_util::stream::unfold(pages, |mut pages| async move {
+            let page = pages
+                .next()
+                .await?
+                .map_err(LoggingTableError::from)
+                .map(|page| {
+                    page.log_streams
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|log_stream| LogStreamInfo {
+                            log_stream_name: log_stream.log_stream_name,
+                            creation_time: log_stream.creation_time,
+                            first_event_timestamp: log_stream.first_event_timestamp,
+                            last_event_timestamp: log_stream.last_event_timestamp,
+                            last_ingestion_time: log_stream.last_ingestion_time,
+                        })
+                        .collect()
+                });
+            Some((page, pages))
+        })
+        .boxed()
+    }
+
+    async fn get_log_events(
+        &self,
+        request: LogEventsRequest,
+    ) -> Result<LogEventsPage, LoggingTableError> {
+        let next_token = request.next_token.clone();
+        let log_events = self
+            .client
+            .get_log_events()
+            .log_group_name(request.log_group_name)
+            .log_stream_name(request.log_stream_name)
+            .start_from_head(request.start_from_head)
+            .set_next_token(request.next_token)
+            .set_start_time(request.start_time)
+            .set_end_time(request.end_time)
+            .set_limit(request.limit)
+            .send()
+            .await
+            .map_err(|e| {
+                // forward tokens expire after about a day
+                let rejected = e
+                    .as_service_error()
+                    .is_some_and(GetLogEventsError::is_invalid_parameter_exception);
+                match next_token {
+                    Some(token) if rejected => LoggingTableError::InvalidNextToken(token),
+                    _ => e.into(),
+                }
+            })?;
+
+        let events = log_events
+            .events
+            .unwrap_or_default()
+            .into_iter()
+            .map(|event| LogEvent {
+                timestamp: event.timestamp,
+                message: event.message,
+                ingestion_time: event.ingestion_time,
+            })
+            .collect();
+        Ok(LogEventsPage {
+            events,
+            next_forward_token: log_events.next_forward_token,
+        })
+    }
+}
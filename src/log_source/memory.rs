@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use futures_util::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    LogEvent, LogEventsPage, LogEventsRequest, LogSource, LogStreamInfo, LogStreamsRequest,
+};
+use crate::logging_table::error::LoggingTableError;
+
+// same page sizes as the CloudWatch API defaults
+const LOG_STREAMS_PAGE_SIZE: usize = 50;
+const LOG_EVENTS_PAGE_SIZE: usize = 10_000;
+
+/// Log source serving canned log groups, loaded from a fixture file or built in code.
+///
+/// Fixture files are JSON:
+/// `{"log_groups": [{"log_group_name": "..", "log_streams": [{"log_stream_name": "..", "events": [..]}]}]}`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MemoryLogSource {
+    pub log_groups: Vec<MemoryLogGroup>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MemoryLogGroup {
+    pub log_group_name: String,
+    #[serde(default)]
+    pub log_streams: Vec<MemoryLogStream>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MemoryLogStream {
+    #[serde(flatten)]
+    pub info: LogStreamInfo,
+    #[serde(default)]
+    pub events: Vec<LogEvent>,
+}
+
+impl MemoryLogSource {
+    pub fn new(log_groups: Vec<MemoryLogGroup>) -> Self {
+        Self { log_groups }
+    }
+
+    pub fn from_file(file_path: impl AsRef<Path>) -> Result<Self, LoggingTableError> {
+        let content = std::fs::read_to_string(file_path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn log_group(&self, log_group_name: &str) -> Result<&MemoryLogGroup, LoggingTableError> {
+        self.log_groups
+            .iter()
+            .find(|x| x.log_group_name == log_group_name)
+            .ok_or_else(|| LoggingTableError::LogGroupNotFound(log_group_name.to_string()))
+    }
+}
+
+#[async_trait]
+impl LogSource for MemoryLogSource {
+    fn describe_log_streams(
+        &self,
+        request: LogStreamsRequest,
+    ) -> BoxStream<'static, Result<Vec<LogStreamInfo>, LoggingTableError>> {
+        let mut log_streams: Vec<LogStreamInfo> = match self.log_group(&request.log_group_name) {
+            Ok(log_group) => log_group
+                .log_streams
+                .iter()
+                .filter(|x| match &request.log_stream_name_prefix {
+                    Some(prefix) => x
+                        .info
+                        .log_stream_name
+                        .as_deref()
+                        .is_some_and(|name| name.starts_with(prefix.as_str())),
+                    None => true,
+                })
+                .map(|x| x.info.clone())
+                .collect(),
+            Err(e) => return futures_util::stream::once(async { Err(e) }).boxed(),
+        };
+        match request.order_by {
+            OrderBy::LastEventTime => log_streams.sort_by_key(|x| x.last_event_timestamp),
+            _ => log_streams.sort_by(|a, b| a.log_stream_name.cmp(&b.log_stream_name)),
+        }
+        if request.descending {
+            log_streams.reverse();
+        }
+
+        let pages: Vec<_> = log_streams
+            .chunks(LOG_STREAMS_PAGE_SIZE)
+            .map(|x| Ok(x.to_vec()))
+            .collect();
+        futures_util::stream::iter(pages).boxed()
+    }
+
+    async fn get_log_events(
+        &self,
+        request: LogEventsRequest,
+    ) -> Result<LogEventsPage, LoggingTableError> {
+        let log_group = self.log_group(&request.log_group_name)?;
+        let events = log_group
+            .log_streams
+            .iter()
+            .find(|x| x.info.log_stream_name.as_deref() == Some(&request.log_stream_name))
+            .map(|x| x.events.as_slice())
+            .unwrap_or_default();
+        let events: Vec<&LogEvent> = events
+            .iter()
+            .filter(|x| {
+                let timestamp = x.timestamp.unwrap_or_default();
+                request.start_time.is_none_or(|start| timestamp >= start)
+                    && request.end_time.is_none_or(|end| timestamp < end)
+            })
+            .collect();
+
+        // tokens are offsets into the stream, at the end the same token is returned again
+        let offset = match &request.next_token {
+            Some(token) => token
+                .strip_prefix("f/")
+                .and_then(|x| x.parse::<usize>().ok())
+                .ok_or_else(|| LoggingTableError::InvalidNextToken(token.clone()))?,
+            None => 0,
+        };
+        let page_size = request
+            .limit
+            .map_or(LOG_EVENTS_PAGE_SIZE, |x| x.max(1) as usize);
+        let page: Vec<LogEvent> = events
+            .into_iter()
+            .skip(offset)
+            .take(page_size)
+            .cloned()
+            .collect();
+        let next_forward_token = Some(format!("f/{}", offset + page.len()));
+        Ok(LogEventsPage {
+            events: page,
+            next_forward_token,
+        })
+    }
+}
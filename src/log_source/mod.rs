@@ -0,0 +1,73 @@
+mod aws;
+mod memory;
+
+pub use aws::*;
+pub use memory::*;
+
+use async_trait::async_trait;
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use futures_util::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+
+use crate::logging_table::error::LoggingTableError;
+
+/// Source of log streams and log events, CloudWatch in production and canned data in tests.
+#[async_trait]
+pub trait LogSource: Send + Sync {
+    /// Lists the log streams of a group, one item per page.
+    fn describe_log_streams(
+        &self,
+        request: LogStreamsRequest,
+    ) -> BoxStream<'static, Result<Vec<LogStreamInfo>, LoggingTableError>>;
+
+    /// Fetches one page of events, callers follow `next_forward_token` until it stops changing.
+    /// Unknown and expired tokens fail with `LoggingTableError::InvalidNextToken`.
+    async fn get_log_events(
+        &self,
+        request: LogEventsRequest,
+    ) -> Result<LogEventsPage, LoggingTableError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct LogStreamsRequest {
+    pub log_group_name: String,
+    pub log_stream_name_prefix: Option<String>,
+    pub order_by: OrderBy,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEventsRequest {
+    pub log_group_name: String,
+    pub log_stream_name: String,
+    pub start_from_head: bool,
+    pub next_token: Option<String>,
+    // start_time is inclusive and end_time exclusive, as in the GetLogEvents API
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    // maximum number of events per page
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogEventsPage {
+    pub events: Vec<LogEvent>,
+    pub next_forward_token: Option<String>,
+}
+
+// i64 expressed as the number of milliseconds after Jan 1, 1970 00:00:00 UTC
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LogStreamInfo {
+    pub log_stream_name: Option<String>,
+    pub creation_time: Option<i64>,
+    pub first_event_timestamp: Option<i64>,
+    pub last_event_timestamp: Option<i64>,
+    pub last_ingestion_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LogEvent {
+    pub timestamp: Option<i64>,
+    pub message: Option<String>,
+    pub ingestion_time: Option<i64>,
+}
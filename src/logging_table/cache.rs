@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, NaiveDate};
+use datafusion::{
+    arrow::{array::UInt32Array, compute::take_record_batch},
+    datasource::{
+        file_format::parquet::ParquetFormat,
+        listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
+        TableProvider,
+    },
+    parquet::arrow::AsyncArrowWriter,
+};
+use tokio::fs::File;
+use uuid::Uuid;
+
+use super::error::LoggingTableError;
+use super::{Checkpoints, LoggingTable};
+
+/// Local Parquet cache of the ingested events, laid out as
+/// `<dir>/log_group=<name>/date=<YYYY-MM-DD>/part-<uuid>.parquet` with the log group
+/// name URL safe base64 encoded. Every ingestion appends new files, existing files
+/// are never rewritten.
+#[derive(Debug, Clone)]
+pub struct LogCache {
+    dir: PathBuf,
+}
+
+impl LogCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Table over every cached file, files added later are picked up by the next scan.
+    pub async fn table(&self) -> Result<Arc<dyn TableProvider>, LoggingTableError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let url = ListingTableUrl::parse(self.dir.to_string_lossy())?;
+        let options =
+            ListingOptions::new(Arc::new(ParquetFormat::default())).with_file_extension(".parquet");
+        let config = ListingTableConfig::new(url)
+            .with_listing_options(options)
+            .with_schema(Arc::new(LoggingTable::schema()));
+        Ok(Arc::new(ListingTable::try_new(config)?))
+    }
+
+    /// Writes the records, one new file per log group and day, and returns the file paths.
+    pub async fn write(&self, records: &[LoggingTable]) -> Result<Vec<PathBuf>, LoggingTableError> {
+        let mut partitions: BTreeMap<(&str, NaiveDate), Vec<u32>> = BTreeMap::new();
+        for (i, record) in records.iter().enumerate() {
+            let log_group_name = record.log_group_name.as_deref().unwrap_or_default();
+            let day = record
+                .timestamp
+                .and_then(DateTime::from_timestamp_millis)
+                .unwrap_or_default()
+                .date_naive();
+            partitions
+                .entry((log_group_name, day))
+                .or_default()
+                .push(i as u32);
+        }
+
+        let batch = LoggingTable::to_record_batch(records)?;
+        let mut files = vec![];
+        for ((log_group_name, day), indices) in partitions {
+            let batch = take_record_batch(&batch, &UInt32Array::from(indices))?;
+            let dir = self.partition_dir(log_group_name, day);
+            tokio::fs::create_dir_all(&dir).await?;
+            // written under a name the table ignores, then renamed so scans never see half a file
+            let path = dir.join(format!("part-{}.parquet", Uuid::new_v4()));
+            let tmp_path = path.with_extension("parquet.tmp");
+            let file = File::create(&tmp_path).await?;
+            let mut writer = AsyncArrowWriter::try_new(file, batch.schema(), None)?;
+            writer.write(&batch).await?;
+            writer.close().await?;
+            tokio::fs::rename(&tmp_path, &path).await?;
+            files.push(path);
+        }
+        Ok(files)
+    }
+
+    /// Reads the checkpoints and deletes the files they do not list, written by an
+    /// ingestion that stopped before its checkpoints. Without checkpoints the cache is
+    /// empty.
+    pub async fn read_checkpoints(&self) -> Result<Checkpoints, LoggingTableError> {
+        let checkpoints = Checkpoints::load(&self.checkpoints_path()).await?;
+        for path in self.files().await? {
+            if !checkpoints.files().contains(&self.relative_path(&path)) {
+                tracing::warn!(path = %path.display(), "deleting uncommitted cache file");
+                tokio::fs::remove_file(&path).await?;
+            }
+        }
+        Ok(checkpoints)
+    }
+
+    /// Commits the written files by listing them in the checkpoints, then writes the
+    /// checkpoints. A crash in between leaves files the checkpoints do not list, they are
+    /// deleted on the next start and their events ingested again.
+    pub async fn write_checkpoints(
+        &self,
+        checkpoints: &mut Checkpoints,
+        files: &[PathBuf],
+    ) -> Result<(), LoggingTableError> {
+        checkpoints.add_files(files.iter().map(|path| self.relative_path(path)));
+        tokio::fs::create_dir_all(&self.dir).await?;
+        checkpoints.save(&self.checkpoints_path()).await
+    }
+
+    // every cached file, whether committed or not
+    async fn files(&self) -> Result<Vec<PathBuf>, LoggingTableError> {
+        let mut files = vec![];
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|x| x == "parquet") {
+                    files.push(path);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn checkpoints_path(&self) -> PathBuf {
+        self.dir.join("checkpoints.json")
+    }
+
+    // `=` segments are the only subdirectories a listing table descends into by default
+    fn partition_dir(&self, log_group_name: &str, day: NaiveDate) -> PathBuf {
+        Path::new(&self.dir)
+            .join(format!(
+                "log_group={}",
+                URL_SAFE_NO_PAD.encode(log_group_name)
+            ))
+            .join(format!("date={day}"))
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::LoggingTableError;
+
+/// Where the next ingestion of a log stream resumes.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamCheckpoint {
+    // forward token of the first page that was not completely ingested
+    pub next_forward_token: Option<String>,
+    // events of that page that were already ingested, only set for truncated streams
+    pub skip_events: usize,
+    // lastIngestionTime of the stream once it was read to the end, None for truncated streams
+    pub last_ingestion_time: Option<i64>,
+    // timestamp of the last ingested event and the number of ingested events sharing it,
+    // the stream resumes there when its forward token expired
+    pub last_timestamp: Option<i64>,
+    pub last_timestamp_events: usize,
+}
+
+/// Stream checkpoints by log group and log stream name, and the cache files they cover.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Checkpoints {
+    log_groups: HashMap<String, HashMap<String, StreamCheckpoint>>,
+    // cache files relative to the cache directory
+    files: BTreeSet<String>,
+}
+
+impl Checkpoints {
+    /// A missing file means nothing was ingested yet.
+    pub async fn load(file_path: &Path) -> Result<Self, LoggingTableError> {
+        match tokio::fs::read(file_path).await {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, file_path: &Path) -> Result<(), LoggingTableError> {
+        // renamed into place so a crash never leaves a truncated file
+        let tmp_path = file_path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec(self)?).await?;
+        tokio::fs::rename(&tmp_path, file_path).await?;
+        Ok(())
+    }
+
+    /// True once the log group was ingested, even if it has no streams.
+    pub fn contains_log_group(&self, log_group_name: &str) -> bool {
+        self.log_groups.contains_key(log_group_name)
+    }
+
+    pub fn log_group(&self, log_group_name: &str) -> HashMap<String, StreamCheckpoint> {
+        self.log_groups
+            .get(log_group_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn files(&self) -> &BTreeSet<String> {
+        &self.files
+    }
+
+    pub fn add_files(&mut self, files: impl IntoIterator<Item = String>) {
+        self.files.extend(files);
+    }
+
+    pub fn update(&mut self, log_group_name: &str, checkpoints: HashMap<String, StreamCheckpoint>) {
+        self.log_groups
+            .entry(log_group_name.to_string())
+            .or_default()
+            .extend(checkpoints);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(token: &str, last_ingestion_time: Option<i64>) -> StreamCheckpoint {
+        StreamCheckpoint {
+            next_forward_token: Some(token.to_string()),
+            skip_events: 0,
+            last_ingestion_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn update_merges_the_streams() {
+        let mut checkpoints = Checkpoints::default();
+        assert!(!checkpoints.contains_log_group("group"));
+        checkpoints.update("group", HashMap::new());
+        // ingested even without streams
+        assert!(checkpoints.contains_log_group("group"));
+
+        checkpoints.update(
+            "group",
+            HashMap::from([
+                ("a".to_string(), checkpoint("f/1", Some(1))),
+                ("b".to_string(), checkpoint("f/1", Some(1))),
+            ]),
+        );
+        checkpoints.update(
+            "group",
+            HashMap::from([("a".to_string(), checkpoint("f/2", Some(2)))]),
+        );
+        let group = checkpoints.log_group("group");
+        assert_eq!(group["a"], checkpoint("f/2", Some(2)));
+        assert_eq!(group["b"], checkpoint("f/1", Some(1)));
+        assert!(checkpoints.log_group("other").is_empty());
+    }
+
+    #[tokio::test]
+    async fn save_and_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("checkpoints-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoints.json");
+
+        // nothing ingested yet
+        let loaded = Checkpoints::load(&path).await.unwrap();
+        assert!(!loaded.contains_log_group("group"));
+
+        let mut checkpoints = Checkpoints::default();
+        checkpoints.update(
+            "group",
+            HashMap::from([("a".to_string(), checkpoint("f/3", None))]),
+        );
+        checkpoints.add_files(["b.parquet".to_string()]);
+        checkpoints.add_files(["a.parquet".to_string()]);
+        checkpoints.save(&path).await.unwrap();
+        let loaded = Checkpoints::load(&path).await.unwrap();
+        assert_eq!(loaded.log_group("group")["a"], checkpoint("f/3", None));
+        let files: Vec<_> = loaded.files().iter().collect();
+        assert_eq!(files, ["a.parquet", "b.parquet"]);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
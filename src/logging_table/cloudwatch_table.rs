@@ -0,0 +1,709 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::datatypes::SchemaRef,
+    catalog::Session,
+    common::{project_schema, Column, ScalarValue},
+    datasource::{TableProvider, TableType},
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{SendableRecordBatchStream, TaskContext},
+    logical_expr::{
+        expr::{InList, Like},
+        Between, BinaryExpr, Operator, TableProviderFilterPushDown,
+    },
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        execution_plan::{Boundedness, EmissionType},
+        stream::RecordBatchReceiverStreamBuilder,
+        DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::{
+    process_log_groups, IngestionFilter, IngestionOptions, IngestionSummary, LoggingTable,
+};
+use crate::log_source::LogSource;
+
+// fetched pages buffered ahead of the query
+const PAGE_BUFFER: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableMode {
+    // the log groups are ingested up front and served from memory
+    Snapshot,
+    // the log groups are fetched at query time with the query predicates pushed down
+    Lazy,
+}
+
+/// Logging table reading from the log source at query time.
+///
+/// Predicates are translated into API parameters: `timestamp` ranges become
+/// startTime/endTime, `log_stream_name` equality, `IN` lists and `LIKE 'prefix%'`
+/// select the streams, `log_group_name` equality selects the groups and `LIMIT`
+/// becomes the GetLogEvents limit.
+pub struct CloudWatchTable {
+    source: Arc<dyn LogSource>,
+    log_group_names: Vec<String>,
+    options: IngestionOptions,
+    schema: SchemaRef,
+}
+
+impl CloudWatchTable {
+    pub fn new(
+        source: Arc<dyn LogSource>,
+        log_group_names: &[String],
+        options: IngestionOptions,
+    ) -> Self {
+        Self {
+            source,
+            log_group_names: log_group_names.to_vec(),
+            options,
+            schema: Arc::new(LoggingTable::schema()),
+        }
+    }
+}
+
+impl std::fmt::Debug for CloudWatchTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CloudWatchTable")
+            .field("log_group_names", &self.log_group_names)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TableProvider for CloudWatchTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    // translated filters are applied exactly by the source, which lets DataFusion push LIMIT down
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|expr| match PushedFilter::try_from_expr(expr) {
+                Some(_) => TableProviderFilterPushDown::Exact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let mut log_group_names = self.log_group_names.clone();
+        let mut filter = IngestionFilter {
+            limit,
+            ..Default::default()
+        };
+        for expr in filters {
+            match PushedFilter::try_from_expr(expr) {
+                Some(PushedFilter::LogGroupNames(names)) => {
+                    log_group_names.retain(|x| names.contains(x))
+                }
+                Some(pushed) => pushed.apply(&mut filter),
+                None => {}
+            }
+        }
+
+        Ok(Arc::new(CloudWatchExec::try_new(
+            self.source.clone(),
+            log_group_names,
+            self.options.clone(),
+            filter,
+            projection.cloned(),
+            &self.schema,
+        )?))
+    }
+}
+
+/// Log group whose lazy scan was cut short by the ingestion limits, or by failing
+/// streams, the result misses some of its events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialScan {
+    pub log_group_name: String,
+    pub max_streams_reached: bool,
+    // streams cut short by max_events_per_stream or max_bytes_per_stream
+    pub truncated_streams: Vec<String>,
+    // errors by log stream name
+    pub failed_streams: HashMap<String, String>,
+}
+
+/// Scan of a `CloudWatchTable`, the log groups are fetched once executed and every
+/// fetched page becomes a batch.
+pub struct CloudWatchExec {
+    source: Arc<dyn LogSource>,
+    log_group_names: Vec<String>,
+    options: IngestionOptions,
+    filter: IngestionFilter,
+    projection: Option<Vec<usize>>,
+    schema: SchemaRef,
+    properties: PlanProperties,
+    // known once the scan went through every log group
+    partial_scans: Arc<Mutex<Vec<PartialScan>>>,
+}
+
+impl CloudWatchExec {
+    fn try_new(
+        source: Arc<dyn LogSource>,
+        log_group_names: Vec<String>,
+        options: IngestionOptions,
+        filter: IngestionFilter,
+        projection: Option<Vec<usize>>,
+        table_schema: &SchemaRef,
+    ) -> DataFusionResult<Self> {
+        let schema = project_schema(table_schema, projection.as_ref())?;
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Ok(Self {
+            source,
+            log_group_names,
+            options,
+            filter,
+            projection,
+            schema,
+            properties,
+            partial_scans: Arc::default(),
+        })
+    }
+
+    pub fn partial_scans(&self) -> Vec<PartialScan> {
+        self.partial_scans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Debug for CloudWatchExec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("CloudWatchExec")
+            .field("log_group_names", &self.log_group_names)
+            .field("filter", &self.filter)
+            .field("projection", &self.projection)
+            .finish()
+    }
+}
+
+impl DisplayAs for CloudWatchExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "CloudWatchExec: log_group_names={:?}",
+            self.log_group_names
+        )?;
+        let filter = &self.filter;
+        if let Some(names) = &filter.log_stream_names {
+            write!(f, ", log_stream_names={names:?}")?;
+        }
+        if let Some(prefix) = &filter.log_stream_name_prefix {
+            write!(f, ", log_stream_name_prefix={prefix:?}")?;
+        }
+        if let Some(start_time) = filter.start_time {
+            write!(f, ", start_time={start_time}")?;
+        }
+        if let Some(end_time) = filter.end_time {
+            write!(f, ", end_time={end_time}")?;
+        }
+        if let Some(limit) = filter.limit {
+            write!(f, ", limit={limit}")?;
+        }
+        Ok(())
+    }
+}
+
+impl ExecutionPlan for CloudWatchExec {
+    fn name(&self) -> &str {
+        "CloudWatchExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    // dropping the stream aborts the fetch
+    fn execute(
+        &self,
+        _partition: usize,
+        _context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        let mut builder = RecordBatchReceiverStreamBuilder::new(self.schema.clone(), 1);
+        let tx = builder.tx();
+        let source = self.source.clone();
+        let log_group_names = self.log_group_names.clone();
+        let options = self.options.clone();
+        let filter = self.filter.clone();
+        let projection = self.projection.clone();
+        let partial_scans = self.partial_scans.clone();
+        builder.spawn(async move {
+            let (pages_tx, mut pages) = mpsc::channel(PAGE_BUFFER);
+            let ingestion =
+                process_log_groups(source, &log_group_names, &options, &filter, Some(pages_tx));
+            let forward = async {
+                let mut remaining = filter.limit.unwrap_or(usize::MAX);
+                while let Some(batch) = pages.recv().await {
+                    let batch = batch.slice(0, batch.num_rows().min(remaining));
+                    remaining -= batch.num_rows();
+                    let batch = match &projection {
+                        Some(projection) => batch.project(projection),
+                        None => Ok(batch),
+                    };
+                    // the query stopped reading
+                    if tx.send(batch.map_err(DataFusionError::from)).await.is_err() {
+                        return;
+                    }
+                    // dropping the pages stops the ingestion
+                    if remaining == 0 {
+                        return;
+                    }
+                }
+            };
+            // the pages end once every ingestion task is done
+            let ((_, groups), ()) = tokio::join!(ingestion, forward);
+
+            let mut scans = vec![];
+            for group in groups {
+                match group.result {
+                    Ok(summary) => scans.extend(partial_scan(group.log_group_name, summary)),
+                    Err(e) => {
+                        let _ = tx.send(Err(DataFusionError::External(Box::new(e)))).await;
+                        return Ok(());
+                    }
+                }
+            }
+            *partial_scans.lock().unwrap_or_else(|e| e.into_inner()) = scans;
+            Ok(())
+        });
+        Ok(builder.build())
+    }
+}
+
+fn partial_scan(log_group_name: String, summary: IngestionSummary) -> Option<PartialScan> {
+    if !summary.is_partial() && summary.failed_streams.is_empty() {
+        return None;
+    }
+    Some(PartialScan {
+        log_group_name,
+        max_streams_reached: summary.max_streams_reached,
+        truncated_streams: summary.truncated_streams,
+        failed_streams: summary.failed_streams,
+    })
+}
+
+/// Partial scans of every `CloudWatchExec` in an executed plan.
+pub fn partial_scans(plan: &dyn ExecutionPlan) -> Vec<PartialScan> {
+    let mut scans = match plan.as_any().downcast_ref::<CloudWatchExec>() {
+        Some(exec) => exec.partial_scans(),
+        None => vec![],
+    };
+    for child in plan.children() {
+        scans.extend(partial_scans(child.as_ref()));
+    }
+    scans
+}
+
+enum PushedFilter {
+    LogGroupNames(Vec<String>),
+    LogStreamNames(Vec<String>),
+    LogStreamNamePrefix(String),
+    // start inclusive, end exclusive
+    TimeRange(Option<i64>, Option<i64>),
+}
+
+impl PushedFilter {
+    fn try_from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+                let (column, op, value) = match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(column), Expr::Literal(value)) => (column, *op, value),
+                    (Expr::Literal(value), Expr::Column(column)) => (column, op.swap()?, value),
+                    _ => return None,
+                };
+                match (column.name.as_str(), op) {
+                    ("log_group_name", Operator::Eq) => {
+                        Some(Self::LogGroupNames(vec![scalar_to_string(value)?]))
+                    }
+                    ("log_stream_name", Operator::Eq) => {
+                        Some(Self::LogStreamNames(vec![scalar_to_string(value)?]))
+                    }
+                    ("timestamp", op) => {
+                        let millis = scalar_to_millis(value)?;
+                        // bounds at i64::MAX overflow and are left to DataFusion
+                        match op {
+                            Operator::Eq => {
+                                Some(Self::TimeRange(Some(millis), Some(millis.checked_add(1)?)))
+                            }
+                            Operator::Gt => {
+                                Some(Self::TimeRange(Some(millis.checked_add(1)?), None))
+                            }
+                            Operator::GtEq => Some(Self::TimeRange(Some(millis), None)),
+                            Operator::Lt => Some(Self::TimeRange(None, Some(millis))),
+                            Operator::LtEq => {
+                                Some(Self::TimeRange(None, Some(millis.checked_add(1)?)))
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Between(Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            }) => match (expr.as_ref(), low.as_ref(), high.as_ref()) {
+                (Expr::Column(column), Expr::Literal(low), Expr::Literal(high))
+                    if column.name == "timestamp" =>
+                {
+                    let low = scalar_to_millis(low)?;
+                    let high = scalar_to_millis(high)?;
+                    Some(Self::TimeRange(Some(low), Some(high.checked_add(1)?)))
+                }
+                _ => None,
+            },
+            Expr::InList(InList {
+                expr,
+                list,
+                negated: false,
+            }) => {
+                let Expr::Column(column) = expr.as_ref() else {
+                    return None;
+                };
+                let names = list
+                    .iter()
+                    .map(|x| match x {
+                        Expr::Literal(value) => scalar_to_string(value),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                match column.name.as_str() {
+                    "log_group_name" => Some(Self::LogGroupNames(names)),
+                    "log_stream_name" => Some(Self::LogStreamNames(names)),
+                    _ => None,
+                }
+            }
+            Expr::Like(Like {
+                negated: false,
+                expr,
+                pattern,
+                escape_char: None,
+                case_insensitive: false,
+            }) => match (expr.as_ref(), pattern.as_ref()) {
+                (Expr::Column(Column { name, .. }), Expr::Literal(pattern))
+                    if name == "log_stream_name" =>
+                {
+                    let pattern = scalar_to_string(pattern)?;
+                    let prefix = pattern.strip_suffix('%')?;
+                    // only a single trailing wildcard maps onto logStreamNamePrefix
+                    if prefix.is_empty() || prefix.contains(['%', '_', '\\']) {
+                        return None;
+                    }
+                    Some(Self::LogStreamNamePrefix(prefix.to_string()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn apply(self, filter: &mut IngestionFilter) {
+        match self {
+            Self::LogGroupNames(_) => {}
+            Self::LogStreamNames(names) => {
+                filter.log_stream_names = Some(match filter.log_stream_names.take() {
+                    Some(current) => current.into_iter().filter(|x| names.contains(x)).collect(),
+                    None => names,
+                });
+            }
+            Self::LogStreamNamePrefix(prefix) => match &filter.log_stream_name_prefix {
+                // two prefixes are only compatible when one extends the other
+                Some(current) if current.starts_with(&prefix) => {}
+                Some(current) if prefix.starts_with(current.as_str()) => {
+                    filter.log_stream_name_prefix = Some(prefix)
+                }
+                Some(_) => filter.log_stream_names = Some(vec![]),
+                None => filter.log_stream_name_prefix = Some(prefix),
+            },
+            Self::TimeRange(start_time, end_time) => {
+                filter.start_time = filter.start_time.max(start_time);
+                filter.end_time = match (filter.end_time, end_time) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+    }
+}
+
+fn scalar_to_string(value: &ScalarValue) -> Option<String> {
+    match value {
+        ScalarValue::Utf8(Some(x)) | ScalarValue::LargeUtf8(Some(x)) => Some(x.clone()),
+        ScalarValue::Utf8View(Some(x)) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+fn scalar_to_millis(value: &ScalarValue) -> Option<i64> {
+    match value {
+        ScalarValue::Int64(Some(x)) | ScalarValue::TimestampMillisecond(Some(x), _) => Some(*x),
+        ScalarValue::Int32(Some(x)) => Some(*x as i64),
+        ScalarValue::TimestampSecond(Some(x), _) => x.checked_mul(1_000),
+        // sub millisecond bounds can not be expressed exactly in the API
+        ScalarValue::TimestampMicrosecond(Some(x), _) if x % 1_000 == 0 => Some(x / 1_000),
+        ScalarValue::TimestampNanosecond(Some(x), _) if x % 1_000_000 == 0 => Some(x / 1_000_000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use datafusion::physical_plan::collect;
+    use futures_util::stream::BoxStream;
+
+    use super::*;
+    use crate::log_source::{
+        LogEvent, LogEventsPage, LogEventsRequest, LogStreamInfo, LogStreamsRequest,
+        MemoryLogGroup, MemoryLogSource, MemoryLogStream,
+    };
+    use crate::logging_table::error::LoggingTableError;
+
+    // counts the GetLogEvents requests
+    struct CountingLogSource {
+        inner: MemoryLogSource,
+        requests: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LogSource for CountingLogSource {
+        fn describe_log_streams(
+            &self,
+            request: LogStreamsRequest,
+        ) -> BoxStream<'static, Result<Vec<LogStreamInfo>, LoggingTableError>> {
+            self.inner.describe_log_streams(request)
+        }
+
+        async fn get_log_events(
+            &self,
+            request: LogEventsRequest,
+        ) -> Result<LogEventsPage, LoggingTableError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_log_events(request).await
+        }
+    }
+
+    fn counting_source() -> Arc<CountingLogSource> {
+        let events = (0..3)
+            .map(|i| LogEvent {
+                timestamp: Some(1_735_689_600_000 + i),
+                message: Some(format!("event {i}")),
+                ingestion_time: None,
+            })
+            .collect();
+        let stream = MemoryLogStream {
+            info: LogStreamInfo {
+                log_stream_name: Some("stream".to_string()),
+                ..Default::default()
+            },
+            events,
+        };
+        Arc::new(CountingLogSource {
+            inner: MemoryLogSource::new(vec![MemoryLogGroup {
+                log_group_name: "group".to_string(),
+                log_streams: vec![stream],
+            }]),
+            requests: AtomicUsize::default(),
+        })
+    }
+
+    fn context(source: Arc<CountingLogSource>) -> SessionContext {
+        let ctx = SessionContext::new();
+        let table =
+            CloudWatchTable::new(source, &["group".to_string()], IngestionOptions::default());
+        ctx.register_table("logs", Arc::new(table)).unwrap();
+        ctx
+    }
+
+    #[tokio::test]
+    async fn events_are_fetched_during_execution() {
+        let source = counting_source();
+        let ctx = context(source.clone());
+
+        let df = ctx.sql("EXPLAIN SELECT * FROM logs").await.unwrap();
+        df.collect().await.unwrap();
+        assert_eq!(source.requests.load(Ordering::SeqCst), 0);
+
+        let df = ctx.sql("SELECT * FROM logs").await.unwrap();
+        let task_ctx = Arc::new(df.task_ctx());
+        let plan = df.create_physical_plan().await.unwrap();
+        assert_eq!(source.requests.load(Ordering::SeqCst), 0);
+        let batches = collect(plan.clone(), task_ctx).await.unwrap();
+        assert_eq!(batches.iter().map(|x| x.num_rows()).sum::<usize>(), 3);
+        assert!(source.requests.load(Ordering::SeqCst) > 0);
+        assert_eq!(partial_scans(plan.as_ref()), vec![]);
+    }
+
+    fn time_range(expr: Expr) -> Option<(Option<i64>, Option<i64>)> {
+        match PushedFilter::try_from_expr(&expr)? {
+            PushedFilter::TimeRange(start_time, end_time) => Some((start_time, end_time)),
+            _ => panic!("not a time range: {expr}"),
+        }
+    }
+
+    fn millis(x: i64) -> Expr {
+        lit(ScalarValue::TimestampMillisecond(Some(x), None))
+    }
+
+    #[test]
+    fn timestamp_comparisons_become_time_ranges() {
+        let cases = [
+            (col("timestamp").eq(millis(10)), (Some(10), Some(11))),
+            (col("timestamp").gt(millis(10)), (Some(11), None)),
+            (col("timestamp").gt_eq(millis(10)), (Some(10), None)),
+            (col("timestamp").lt(millis(10)), (None, Some(10))),
+            (col("timestamp").lt_eq(millis(10)), (None, Some(11))),
+            // the literal on the left swaps the operator
+            (millis(10).lt(col("timestamp")), (Some(11), None)),
+            (
+                col("timestamp").between(millis(10), millis(20)),
+                (Some(10), Some(21)),
+            ),
+            (
+                col("timestamp").gt_eq(lit(ScalarValue::TimestampSecond(Some(2), None))),
+                (Some(2_000), None),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(time_range(expr.clone()), Some(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn overflowing_timestamp_bounds_are_not_pushed_down() {
+        for expr in [
+            col("timestamp").lt_eq(millis(i64::MAX)),
+            col("timestamp").eq(millis(i64::MAX)),
+            col("timestamp").gt(millis(i64::MAX)),
+            col("timestamp").between(millis(0), millis(i64::MAX)),
+        ] {
+            assert_eq!(time_range(expr.clone()), None, "{expr}");
+        }
+        // the upper bound itself is fine
+        assert_eq!(
+            time_range(col("timestamp").lt(millis(i64::MAX))),
+            Some((None, Some(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn sub_millisecond_bounds_are_not_pushed_down() {
+        let expr = col("timestamp").gt(lit(ScalarValue::TimestampMicrosecond(Some(1_500), None)));
+        assert_eq!(time_range(expr), None);
+    }
+
+    #[test]
+    fn stream_predicates_select_streams() {
+        let filter = |exprs: Vec<Expr>| {
+            let mut filter = IngestionFilter::default();
+            for expr in exprs {
+                PushedFilter::try_from_expr(&expr)
+                    .expect("pushed down")
+                    .apply(&mut filter);
+            }
+            filter
+        };
+
+        let f = filter(vec![
+            col("log_stream_name").in_list(vec![lit("a"), lit("b")], false)
+        ]);
+        assert_eq!(
+            f.log_stream_names,
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        let f = filter(vec![
+            col("log_stream_name").in_list(vec![lit("a"), lit("b")], false),
+            col("log_stream_name").eq(lit("b")),
+        ]);
+        assert_eq!(f.log_stream_names, Some(vec!["b".to_string()]));
+
+        let f = filter(vec![col("log_stream_name").like(lit("2025/01/%"))]);
+        assert_eq!(f.log_stream_name_prefix.as_deref(), Some("2025/01/"));
+        // the longer of two compatible prefixes wins, incompatible ones select nothing
+        let f = filter(vec![
+            col("log_stream_name").like(lit("2025/%")),
+            col("log_stream_name").like(lit("2025/01/%")),
+        ]);
+        assert_eq!(f.log_stream_name_prefix.as_deref(), Some("2025/01/"));
+        let f = filter(vec![
+            col("log_stream_name").like(lit("2025/%")),
+            col("log_stream_name").like(lit("2024/%")),
+        ]);
+        assert_eq!(f.log_stream_names, Some(vec![]));
+
+        let f = filter(vec![
+            col("timestamp").gt_eq(millis(10)),
+            col("timestamp").gt_eq(millis(20)),
+            col("timestamp").lt(millis(30)),
+        ]);
+        assert_eq!((f.start_time, f.end_time), (Some(20), Some(30)));
+    }
+
+    #[test]
+    fn untranslatable_predicates_are_not_pushed_down() {
+        for expr in [
+            col("log_stream_name").like(lit("%a")),
+            col("log_stream_name").like(lit("a_b%")),
+            col("log_stream_name").not_eq(lit("a")),
+            col("log_stream_name").in_list(vec![lit("a")], true),
+            col("message").eq(lit("a")),
+            col("timestamp").not_eq(millis(10)),
+        ] {
+            assert!(PushedFilter::try_from_expr(&expr).is_none(), "{expr}");
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::io::Error as IoError;
+use std::time::Duration;
 
 use aws_sdk_cloudwatchlogs::error::SdkError;
 use aws_sdk_cloudwatchlogs::operation::describe_log_streams::DescribeLogStreamsError;
@@ -7,6 +8,7 @@ use color_eyre::eyre::Report;
 use datafusion::arrow::error::ArrowError;
 use datafusion::error::DataFusionError;
 use datafusion::parquet::errors::ParquetError;
+use datafusion::sql::sqlparser::parser::ParserError;
 use thiserror::Error;
 use tokio::task::JoinError;
 use tracing_subscriber::filter::FromEnvError;
@@ -18,20 +20,41 @@ pub enum LoggingTableError {
     ArrowError(#[from] ArrowError),
 
     #[error("AWS DescribeLogStreams error")]
-    DescribeLogStreamsError(#[from] SdkError<DescribeLogStreamsError>),
+    DescribeLogStreamsError(#[source] Box<SdkError<DescribeLogStreamsError>>),
 
     #[error("AWS GetLogEventsError error")]
-    GetLogEventsError(#[from] SdkError<GetLogEventsError>),
+    GetLogEventsError(#[source] Box<SdkError<GetLogEventsError>>),
 
     #[error("DataFusion error")]
     DataFusionError(#[from] DataFusionError),
 
+    #[error("Every log group failed to ingest")]
+    AllLogGroupsFailed,
+
+    #[error("Ingestion cancelled")]
+    Cancelled,
+
+    #[error("Invalid next token: {0}")]
+    InvalidNextToken(String),
+
     #[error("IO error")]
     IoError(#[from] IoError),
 
+    #[error("Log group not found: {0}")]
+    LogGroupNotFound(String),
+
+    #[error("Missing column: {0}")]
+    MissingColumn(String),
+
+    #[error("Query timed out after {0:?}")]
+    QueryTimeout(Duration),
+
     #[error("Parquet error")]
     ParquetError(#[from] ParquetError),
 
+    #[error("Serde JSON error")]
+    SerdeJsonError(#[from] serde_json::Error),
+
     #[error("TokioJoin error")]
     TokioJoinError(#[from] JoinError),
 
@@ -44,3 +67,37 @@ pub enum LoggingTableError {
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
+
+#[derive(Debug, Error)]
+pub enum QueryValidationError {
+    #[error("Query is empty")]
+    Empty,
+
+    #[error("Multiple statements are not allowed")]
+    MultipleStatements,
+
+    #[error("{0} statements are not allowed, only queries and EXPLAIN are")]
+    NotAllowed(String),
+
+    #[error("Unknown table: {0}")]
+    UnknownTable(String),
+
+    #[error("SQL parse error: {0}")]
+    ParseError(#[from] ParserError),
+
+    #[error("DataFusion error: {0}")]
+    DataFusionError(#[from] DataFusionError),
+}
+
+// the SDK errors are boxed to keep LoggingTableError small
+impl From<SdkError<DescribeLogStreamsError>> for LoggingTableError {
+    fn from(e: SdkError<DescribeLogStreamsError>) -> Self {
+        Self::DescribeLogStreamsError(Box::new(e))
+    }
+}
+
+impl From<SdkError<GetLogEventsError>> for LoggingTableError {
+    fn from(e: SdkError<GetLogEventsError>) -> Self {
+        Self::GetLogEventsError(Box::new(e))
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use datafusion::arrow::array::RecordBatch;
+use tokio::sync::mpsc;
+
+use super::error::LoggingTableError;
+use super::StreamCheckpoint;
+use crate::config::IngestionConfig;
+
+#[derive(Debug, Clone)]
+pub struct IngestionOptions {
+    // upper bound on the number of log streams ingested, None means every stream
+    pub max_streams: Option<usize>,
+    pub order_by: OrderBy,
+    pub descending: bool,
+    // per stream limits, a stream is marked as truncated when one of them is hit
+    pub max_events_per_stream: Option<usize>,
+    pub max_bytes_per_stream: Option<usize>,
+}
+
+impl IngestionOptions {
+    pub fn new(
+        max_streams: Option<usize>,
+        order_by: OrderBy,
+        descending: bool,
+        max_events_per_stream: Option<usize>,
+        max_bytes_per_stream: Option<usize>,
+    ) -> Self {
+        Self {
+            max_streams,
+            order_by,
+            descending,
+            max_events_per_stream,
+            max_bytes_per_stream,
+        }
+    }
+}
+
+impl Default for IngestionOptions {
+    fn default() -> Self {
+        Self::from(&IngestionConfig::default())
+    }
+}
+
+/// Restricts what is fetched from the log source, used to push query predicates down.
+#[derive(Debug, Default, Clone)]
+pub struct IngestionFilter {
+    pub log_stream_names: Option<Vec<String>>,
+    pub log_stream_name_prefix: Option<String>,
+    // milliseconds after Jan 1, 1970 00:00:00 UTC, start_time inclusive and end_time exclusive
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    // maximum number of events fetched per log stream
+    pub limit: Option<usize>,
+    // resume points by log stream name, streams whose lastIngestionTime did not move are skipped
+    pub checkpoints: Arc<HashMap<String, StreamCheckpoint>>,
+}
+
+/// Receives the events of every fetched page in `LoggingTable::schema`, as soon as it is
+/// fetched, instead of the returned records. Lazy scans stream them.
+pub type PageSender = mpsc::Sender<RecordBatch>;
+
+#[derive(Debug, Default, Clone)]
+pub struct IngestionSummary {
+    pub pages_visited: usize,
+    pub streams_visited: usize,
+    // max_streams was reached, the other streams were left out
+    pub max_streams_reached: bool,
+    // streams left out because nothing was ingested since their checkpoint
+    pub streams_skipped: usize,
+    pub events_ingested: usize,
+    pub bytes_ingested: usize,
+    // streams cut short by max_events_per_stream or max_bytes_per_stream
+    pub truncated_streams: Vec<String>,
+    // errors of the streams that failed, by log stream name, their checkpoints are not updated
+    pub failed_streams: HashMap<String, String>,
+    // checkpoints of the ingested streams, by log stream name
+    pub checkpoints: HashMap<String, StreamCheckpoint>,
+}
+
+impl IngestionSummary {
+    pub fn is_partial(&self) -> bool {
+        self.max_streams_reached || !self.truncated_streams.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct LogGroupIngestion {
+    pub log_group_name: String,
+    pub result: Result<IngestionSummary, LoggingTableError>,
+}
+
+impl LogGroupIngestion {
+    pub fn new(
+        log_group_name: String,
+        result: Result<IngestionSummary, LoggingTableError>,
+    ) -> Self {
+        Self {
+            log_group_name,
+            result,
+        }
+    }
+}
@@ -1,22 +1,44 @@
+use std::ops::ControlFlow;
 use std::sync::Arc;
 
-use aws_sdk_cloudwatchlogs::Client;
+use aws_sdk_cloudwatchlogs::types::OrderBy;
 use datafusion::{
     arrow::{
-        array::{AsArray, Int64Array, RecordBatch, StringArray},
-        datatypes::{DataType, Field, Int64Type, Schema},
+        array::{AsArray, RecordBatch, StringArray, TimestampMillisecondArray},
+        datatypes::{DataType, Field, Schema, TimeUnit, TimestampMillisecondType},
     },
     prelude::*,
+    sql::{
+        parser::{DFParser, Statement as DFStatement},
+        sqlparser::{
+            ast::{Query, SetExpr, Statement, Visit, Visitor},
+            dialect::GenericDialect,
+            parser::{Parser, ParserError},
+            tokenizer::Location,
+        },
+    },
 };
 use itertools::izip;
 use serde::{Deserialize, Serialize};
 use tokio_stream::StreamExt;
+use tokio_util::task::AbortOnDropHandle;
+
+use super::error::{LoggingTableError, QueryValidationError};
+use super::{
+    IngestionFilter, IngestionOptions, IngestionSummary, LogGroupIngestion, PageSender,
+    StreamCheckpoint,
+};
+use crate::log_source::{LogEventsRequest, LogSource, LogStreamInfo, LogStreamsRequest};
+use crate::utils::tracing::{error_chain, log_error_chain};
 
-use super::error::LoggingTableError;
+const UTC: &str = "UTC";
+// GetLogEvents does not accept a larger limit
+const MAX_LOG_EVENTS_LIMIT: usize = 10_000;
 
-// i64 expressed as the number of milliseconds after Jan 1, 1970 00:00:00 UTC
+// times are i64 milliseconds after Jan 1, 1970 00:00:00 UTC, typed as UTC timestamps in the table
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LoggingTable {
+    pub log_group_name: Option<String>,
     pub log_stream_name: Option<String>,
     pub log_creation_time: Option<i64>,
     pub first_event_timestamp: Option<i64>,
@@ -28,7 +50,9 @@ pub struct LoggingTable {
 }
 
 impl LoggingTable {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        log_group_name: Option<String>,
         log_stream_name: Option<String>,
         log_creation_time: Option<i64>,
         first_event_timestamp: Option<i64>,
@@ -39,6 +63,7 @@ impl LoggingTable {
         ingestion_time: Option<i64>,
     ) -> Self {
         Self {
+            log_group_name,
             log_stream_name,
             log_creation_time,
             first_event_timestamp,
@@ -52,22 +77,34 @@ impl LoggingTable {
 
     pub fn schema() -> Schema {
         Schema::new(vec![
+            Field::new("log_group_name", DataType::Utf8, true),
             Field::new("log_stream_name", DataType::Utf8, true),
-            Field::new("log_creation_time", DataType::Int64, true),
-            Field::new("first_event_timestamp", DataType::Int64, true),
-            Field::new("last_event_timestamp", DataType::Int64, true),
-            Field::new("last_ingestion_time", DataType::Int64, true),
-            Field::new("timestamp", DataType::Int64, true),
+            Field::new("log_creation_time", Self::timestamp_type(), true),
+            Field::new("first_event_timestamp", Self::timestamp_type(), true),
+            Field::new("last_event_timestamp", Self::timestamp_type(), true),
+            Field::new("last_ingestion_time", Self::timestamp_type(), true),
+            Field::new("timestamp", Self::timestamp_type(), true),
             Field::new("message", DataType::Utf8, true),
-            Field::new("ingestion_time", DataType::Int64, true),
+            Field::new("ingestion_time", Self::timestamp_type(), true),
         ])
     }
 
+    // CloudWatch times are milliseconds after Jan 1, 1970 00:00:00 UTC
+    pub fn timestamp_type() -> DataType {
+        DataType::Timestamp(TimeUnit::Millisecond, Some(UTC.into()))
+    }
+
     pub async fn to_df(
         ctx: &SessionContext,
-        records: &Vec<Self>,
+        records: &[Self],
     ) -> Result<DataFrame, LoggingTableError> {
+        let batch = Self::to_record_batch(records)?;
+        Ok(ctx.read_batch(batch)?)
+    }
+
+    pub fn to_record_batch(records: &[Self]) -> Result<RecordBatch, LoggingTableError> {
         let schema = Self::schema();
+        let mut log_group_names = vec![];
         let mut log_stream_names = vec![];
         let mut log_creation_times = vec![];
         let mut first_event_timestamps = vec![];
@@ -78,6 +115,7 @@ impl LoggingTable {
         let mut ingestion_times = vec![];
 
         for record in records {
+            log_group_names.push(record.log_group_name.clone());
             log_stream_names.push(record.log_stream_name.clone());
             log_creation_times.push(record.log_creation_time);
             first_event_timestamps.push(record.first_event_timestamp);
@@ -91,17 +129,20 @@ impl LoggingTable {
         let batch = RecordBatch::try_new(
             Arc::new(schema),
             vec![
+                Arc::new(StringArray::from(log_group_names)),
                 Arc::new(StringArray::from(log_stream_names)),
-                Arc::new(Int64Array::from(log_creation_times)),
-                Arc::new(Int64Array::from(first_event_timestamps)),
-                Arc::new(Int64Array::from(last_event_timestamps)),
-                Arc::new(Int64Array::from(last_ingestion_times)),
-                Arc::new(Int64Array::from(timestamps)),
+                Arc::new(TimestampMillisecondArray::from(log_creation_times).with_timezone(UTC)),
+                Arc::new(
+                    TimestampMillisecondArray::from(first_event_timestamps).with_timezone(UTC),
+                ),
+                Arc::new(TimestampMillisecondArray::from(last_event_timestamps).with_timezone(UTC)),
+                Arc::new(TimestampMillisecondArray::from(last_ingestion_times).with_timezone(UTC)),
+                Arc::new(TimestampMillisecondArray::from(timestamps).with_timezone(UTC)),
                 Arc::new(StringArray::from(messages)),
-                Arc::new(Int64Array::from(ingestion_times)),
+                Arc::new(TimestampMillisecondArray::from(ingestion_times).with_timezone(UTC)),
             ],
         )?;
-        Ok(ctx.read_batch(batch)?)
+        Ok(batch)
     }
 }
 
@@ -110,16 +151,18 @@ impl LoggingTable {
         let mut stream = df.execute_stream().await?;
         let mut records = vec![];
         while let Some(batch) = stream.next().await.transpose()? {
-            let log_stream_names = batch.column(0).as_string::<i32>();
-            let log_creation_times = batch.column(1).as_primitive::<Int64Type>();
-            let first_event_timestamps = batch.column(2).as_primitive::<Int64Type>();
-            let last_event_timestamps = batch.column(3).as_primitive::<Int64Type>();
-            let last_ingestion_times = batch.column(4).as_primitive::<Int64Type>();
-            let timestamps = batch.column(5).as_primitive::<Int64Type>();
-            let messages = batch.column(6).as_string::<i32>();
-            let ingestion_times = batch.column(7).as_primitive::<Int64Type>();
+            let log_group_names = batch.column(0).as_string::<i32>();
+            let log_stream_names = batch.column(1).as_string::<i32>();
+            let log_creation_times = batch.column(2).as_primitive::<TimestampMillisecondType>();
+            let first_event_timestamps = batch.column(3).as_primitive::<TimestampMillisecondType>();
+            let last_event_timestamps = batch.column(4).as_primitive::<TimestampMillisecondType>();
+            let last_ingestion_times = batch.column(5).as_primitive::<TimestampMillisecondType>();
+            let timestamps = batch.column(6).as_primitive::<TimestampMillisecondType>();
+            let messages = batch.column(7).as_string::<i32>();
+            let ingestion_times = batch.column(8).as_primitive::<TimestampMillisecondType>();
 
             for (
+                log_group_name,
                 log_stream_name,
                 log_creation_time,
                 first_event_timestamp,
@@ -129,6 +172,7 @@ impl LoggingTable {
                 message,
                 ingestion_time,
             ) in izip!(
+                log_group_names,
                 log_stream_names,
                 log_creation_times,
                 first_event_timestamps,
@@ -139,6 +183,7 @@ impl LoggingTable {
                 ingestion_times
             ) {
                 records.push(Self {
+                    log_group_name: log_group_name.map(|x| x.to_string()),
                     log_stream_name: log_stream_name.map(|x| x.to_string()),
                     log_creation_time,
                     first_event_timestamp,
@@ -154,79 +199,597 @@ impl LoggingTable {
     }
 }
 
+/// Ingests every log group into one set of records, or sends them page by page to `pages`.
+/// A failing group does not abort the others, its error is reported in the returned
+/// `LogGroupIngestion`.
+pub async fn process_log_groups(
+    source: Arc<dyn LogSource>,
+    log_group_names: &[String],
+    options: &IngestionOptions,
+    filter: &IngestionFilter,
+    pages: Option<PageSender>,
+) -> (Vec<LoggingTable>, Vec<LogGroupIngestion>) {
+    let mut tasks = vec![];
+    for log_group_name in log_group_names {
+        let source = source.clone();
+        let name = log_group_name.clone();
+        let options = options.clone();
+        let filter = filter.clone();
+        let pages = pages.clone();
+        // tasks are aborted when the ingestion is dropped, e.g. by a cancelled query
+        let task = AbortOnDropHandle::new(tokio::spawn(async move {
+            process_logging_table(source, &name, &options, &filter, pages).await
+        }));
+        tasks.push((log_group_name.clone(), task));
+    }
+
+    let mut records = vec![];
+    let mut groups = vec![];
+    for (log_group_name, task) in tasks {
+        let result = match task.await {
+            Ok(Ok((logging_table, summary))) => {
+                records.extend(logging_table);
+                Ok(summary)
+            }
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(e.into()),
+        };
+        groups.push(LogGroupIngestion::new(log_group_name, result));
+    }
+    (records, groups)
+}
+
 pub async fn process_logging_table(
-    client: Client,
+    source: Arc<dyn LogSource>,
     log_group_name: &str,
-) -> Result<Vec<LoggingTable>, LoggingTableError> {
-    let log_streams = client
-        .describe_log_streams()
-        .log_group_name(log_group_name)
-        .send()
-        .await?;
+    options: &IngestionOptions,
+    filter: &IngestionFilter,
+    pages: Option<PageSender>,
+) -> Result<(Vec<LoggingTable>, IngestionSummary), LoggingTableError> {
+    let mut summary = IngestionSummary::default();
+    let mut log_streams = vec![];
+    // DescribeLogStreams rejects a stream name prefix combined with LastEventTime ordering
+    let order_by = match filter.log_stream_name_prefix {
+        Some(_) => OrderBy::LogStreamName,
+        None => options.order_by.clone(),
+    };
+    let mut stream_pages = source.describe_log_streams(LogStreamsRequest {
+        log_group_name: log_group_name.to_string(),
+        log_stream_name_prefix: filter.log_stream_name_prefix.clone(),
+        order_by,
+        descending: options.descending,
+    });
+    while let Some(page) = stream_pages.next().await {
+        let page = page?;
+        summary.pages_visited += 1;
+        log_streams.extend(page.into_iter().filter(|log_stream| {
+            let name_matches = match (&filter.log_stream_names, &log_stream.log_stream_name) {
+                (Some(names), Some(name)) => names.contains(name),
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+            // streams created after the requested window can not hold matching events
+            let time_matches = match (filter.end_time, log_stream.first_event_timestamp) {
+                (Some(end_time), Some(first_event_timestamp)) => first_event_timestamp < end_time,
+                _ => true,
+            };
+            name_matches && time_matches
+        }));
+        if let Some(max_streams) = options.max_streams {
+            if log_streams.len() >= max_streams {
+                log_streams.truncate(max_streams);
+                summary.max_streams_reached = true;
+                break;
+            }
+        }
+    }
+    summary.streams_visited = log_streams.len();
 
     let mut tasks = vec![];
-    for log_stream in log_streams.log_streams() {
-        if let Some(log_stream_name) = log_stream.log_stream_name() {
-            let task = tokio::spawn(processs_log(
-                client.clone(),
-                log_group_name.to_string(),
-                log_stream_name.to_string(),
+    for log_stream in log_streams {
+        let Some(log_stream_name) = log_stream.log_stream_name.clone() else {
+            continue;
+        };
+        let checkpoint = filter.checkpoints.get(&log_stream_name).cloned();
+        let unchanged = checkpoint.as_ref().is_some_and(|checkpoint| {
+            checkpoint.last_ingestion_time.is_some()
+                && checkpoint.last_ingestion_time == log_stream.last_ingestion_time
+        });
+        if unchanged {
+            summary.streams_skipped += 1;
+            continue;
+        }
+        let task = AbortOnDropHandle::new(tokio::spawn(processs_log(
+            source.clone(),
+            log_group_name.to_string(),
+            log_stream,
+            checkpoint,
+            options.clone(),
+            filter.clone(),
+            pages.clone(),
+        )));
+        tasks.push((log_stream_name, task));
+    }
+
+    let mut records = vec![];
+    for (log_stream_name, task) in tasks {
+        // a failing stream does not fail the log group, it is retried on the next ingestion
+        let stream = match task.await.map_err(LoggingTableError::from).and_then(|x| x) {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::error!(
+                    log_group_name,
+                    log_stream_name,
+                    "log stream ingestion failed"
+                );
+                log_error_chain(&e);
+                summary
+                    .failed_streams
+                    .insert(log_stream_name, error_chain(&e));
+                continue;
+            }
+        };
+        summary.events_ingested += stream.events;
+        summary.bytes_ingested += stream.bytes;
+        if stream.truncated {
+            summary
+                .truncated_streams
+                .push(stream.log_stream_name.clone());
+        }
+        summary
+            .checkpoints
+            .insert(stream.log_stream_name, stream.checkpoint);
+        records.extend(stream.records);
+    }
+    Ok((records, summary))
+}
+
+struct StreamIngestion {
+    log_stream_name: String,
+    // empty when the pages were sent
+    records: Vec<LoggingTable>,
+    events: usize,
+    bytes: usize,
+    truncated: bool,
+    checkpoint: StreamCheckpoint,
+}
+
+async fn processs_log(
+    source: Arc<dyn LogSource>,
+    log_group_name: String,
+    log_stream: LogStreamInfo,
+    checkpoint: Option<StreamCheckpoint>,
+    options: IngestionOptions,
+    filter: IngestionFilter,
+    pages: Option<PageSender>,
+) -> Result<StreamIngestion, LoggingTableError> {
+    let log_stream_name = log_stream.log_stream_name.clone().unwrap_or_default();
+    let mut res = vec![];
+    let mut events = 0;
+    let mut bytes = 0;
+    let mut truncated = false;
+    let checkpoint = checkpoint.unwrap_or_default();
+    let mut next_token = checkpoint.next_forward_token;
+    let mut last_timestamp = checkpoint.last_timestamp;
+    let mut last_timestamp_events = checkpoint.last_timestamp_events;
+    let (mut start_time, mut skip_events) = match next_token {
+        Some(_) => (filter.start_time, checkpoint.skip_events),
+        None => resume_at(filter.start_time, last_timestamp, last_timestamp_events),
+    };
+    let mut resumed = false;
+    let mut page_token;
+    // index in the current page of the first event that was not ingested
+    let mut stopped_at = None;
+
+    // sources return the same forward token once the end of the stream is reached
+    'pages: loop {
+        page_token = next_token.clone();
+        let page = source
+            .get_log_events(LogEventsRequest {
+                log_group_name: log_group_name.clone(),
+                log_stream_name: log_stream_name.clone(),
+                start_from_head: true,
+                next_token: next_token.clone(),
+                start_time,
+                end_time: filter.end_time,
+                limit: filter
+                    .limit
+                    .map(|x| x.clamp(1, MAX_LOG_EVENTS_LIMIT) as i32),
+            })
+            .await;
+        let log_events = match page {
+            // tokens expire after about a day
+            Err(LoggingTableError::InvalidNextToken(_)) if !resumed => {
+                tracing::warn!(
+                    log_group_name,
+                    log_stream_name,
+                    ?last_timestamp,
+                    "next token rejected, resuming at the last ingested event"
+                );
+                resumed = true;
+                next_token = None;
+                (start_time, skip_events) =
+                    resume_at(start_time, last_timestamp, last_timestamp_events);
+                continue;
+            }
+            page => page?,
+        };
+
+        let page_len = log_events.events.len();
+        for (i, event) in log_events.events.into_iter().enumerate().skip(skip_events) {
+            if filter.limit.is_some_and(|max| events >= max) {
+                stopped_at = Some(i);
+                break 'pages;
+            }
+            let event_bytes = event.message.as_deref().map(str::len).unwrap_or_default();
+            let events_limit_hit = options
+                .max_events_per_stream
+                .is_some_and(|max| events >= max);
+            let bytes_limit_hit = options
+                .max_bytes_per_stream
+                .is_some_and(|max| bytes + event_bytes > max);
+            if events_limit_hit || bytes_limit_hit {
+                truncated = true;
+                stopped_at = Some(i);
+                break 'pages;
+            }
+
+            let logging_table = LoggingTable::new(
+                Some(log_group_name.clone()),
+                Some(log_stream_name.clone()),
                 log_stream.creation_time,
                 log_stream.first_event_timestamp,
                 log_stream.last_event_timestamp,
                 log_stream.last_ingestion_time,
-                true,
-            ));
-            tasks.push(task);
+                event.timestamp,
+                event.message,
+                event.ingestion_time,
+            );
+            match event.timestamp == last_timestamp {
+                true => last_timestamp_events += 1,
+                false => {
+                    last_timestamp = event.timestamp;
+                    last_timestamp_events = 1;
+                }
+            }
+            res.push(logging_table);
+            events += 1;
+            bytes += event_bytes;
+        }
+        skip_events = skip_events.saturating_sub(page_len);
+        if let Some(pages) = &pages {
+            send_page(pages, &mut res).await?;
+        }
+
+        match log_events.next_forward_token {
+            Some(token) if next_token.as_ref() != Some(&token) => next_token = Some(token),
+            _ => break,
         }
     }
 
-    let mut records = vec![];
-    for task in tasks {
-        let logging_table = task.await??;
-        records.extend(logging_table);
+    if let Some(pages) = &pages {
+        send_page(pages, &mut res).await?;
     }
-    Ok(records)
+
+    // a stream read to the end resumes from its last token, a cut short one from the
+    // start of the page it stopped in
+    let checkpoint = match stopped_at {
+        Some(skip_events) => StreamCheckpoint {
+            next_forward_token: page_token,
+            skip_events,
+            last_ingestion_time: None,
+            last_timestamp,
+            last_timestamp_events,
+        },
+        None => StreamCheckpoint {
+            next_forward_token: next_token,
+            skip_events: 0,
+            last_ingestion_time: log_stream.last_ingestion_time,
+            last_timestamp,
+            last_timestamp_events,
+        },
+    };
+    Ok(StreamIngestion {
+        log_stream_name,
+        records: res,
+        events,
+        bytes,
+        truncated,
+        checkpoint,
+    })
 }
 
-async fn processs_log(
-    client: Client,
-    log_group_name: String,
-    log_stream_name: String,
-    log_creation_time: Option<i64>,
-    first_event_timestamp: Option<i64>,
-    last_event_timestamp: Option<i64>,
-    last_ingestion_time: Option<i64>,
-    start_from_head: bool,
-) -> Result<Vec<LoggingTable>, LoggingTableError> {
-    let log_events = client
-        .get_log_events()
-        .log_group_name(log_group_name)
-        .log_stream_name(&log_stream_name)
-        .start_from_head(start_from_head)
-        .send()
-        .await?;
+// takes the records, a closed receiver means the scan was dropped
+async fn send_page(
+    pages: &PageSender,
+    records: &mut Vec<LoggingTable>,
+) -> Result<(), LoggingTableError> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let batch = LoggingTable::to_record_batch(&std::mem::take(records))?;
+    pages
+        .send(batch)
+        .await
+        .map_err(|_| LoggingTableError::Cancelled)
+}
 
-    let mut res = vec![];
-    for event in log_events.events() {
-        let logging_table = LoggingTable::new(
-            Some(log_stream_name.clone()),
-            log_creation_time,
-            first_event_timestamp,
-            last_event_timestamp,
-            last_ingestion_time,
-            event.timestamp,
-            event.message.clone(),
-            event.ingestion_time,
-        );
-        res.push(logging_table);
+// Streams without a forward token resume at their last ingested event, without the
+// events of that millisecond they already ingested. Returns the start time and the
+// number of events to skip.
+fn resume_at(
+    start_time: Option<i64>,
+    last_timestamp: Option<i64>,
+    last_timestamp_events: usize,
+) -> (Option<i64>, usize) {
+    let resumed_at = start_time.max(last_timestamp);
+    match resumed_at == last_timestamp {
+        true => (resumed_at, last_timestamp_events),
+        false => (resumed_at, 0),
+    }
+}
+
+/// Parses the query and checks that it is a single read-only statement (a query or an
+/// EXPLAIN of one) that only references tables registered in `ctx`.
+pub fn query_validator(
+    ctx: &SessionContext,
+    query: &str,
+) -> Result<DFStatement, QueryValidationError> {
+    let mut statements = DFParser::parse_sql(query).map_err(|e| locate_parse_error(query, e))?;
+    if statements.len() > 1 {
+        return Err(QueryValidationError::MultipleStatements);
+    }
+    let statement = statements.pop_front().ok_or(QueryValidationError::Empty)?;
+    validate_statement(&statement)?;
+
+    for table in ctx.state().resolve_table_references(&statement)? {
+        if !ctx.table_exist(table.clone())? {
+            return Err(QueryValidationError::UnknownTable(table.to_string()));
+        }
+    }
+    Ok(statement)
+}
+
+// DFParser tokenizes without locations, sqlparser alone tells where the query failed to parse
+fn locate_parse_error(query: &str, e: ParserError) -> ParserError {
+    let res = Parser::new(&GenericDialect {})
+        .try_with_sql(query)
+        .and_then(|mut parser| parser.parse_statements());
+    match res {
+        // the end of the query has no token, hence no location
+        Err(ParserError::ParserError(message)) if message.ends_with("found: EOF") => {
+            let end = Location {
+                line: query.split('\n').count() as u64,
+                column: query
+                    .rsplit('\n')
+                    .next()
+                    .unwrap_or_default()
+                    .chars()
+                    .count() as u64
+                    + 1,
+            };
+            ParserError::ParserError(format!("{message}{end}"))
+        }
+        Err(located) if located.to_string().contains(" at Line: ") => located,
+        _ => e,
+    }
+}
+
+fn validate_statement(statement: &DFStatement) -> Result<(), QueryValidationError> {
+    match statement {
+        DFStatement::Statement(statement) => match statement.as_ref() {
+            Statement::Query(query) => validate_query(query),
+            Statement::Explain { statement, .. } => match statement.as_ref() {
+                Statement::Query(query) => validate_query(query),
+                statement => Err(QueryValidationError::NotAllowed(statement_kind(statement))),
+            },
+            statement => Err(QueryValidationError::NotAllowed(statement_kind(statement))),
+        },
+        DFStatement::Explain(explain) => validate_statement(&explain.statement),
+        DFStatement::CreateExternalTable(_) => {
+            Err(QueryValidationError::NotAllowed("DDL".to_string()))
+        }
+        DFStatement::CopyTo(_) => Err(QueryValidationError::NotAllowed("COPY".to_string())),
+    }
+}
+
+// walks CTEs, subqueries and set operations, which may hide statements as well
+fn validate_query(query: &Query) -> Result<(), QueryValidationError> {
+    match query.visit(&mut ReadOnlyVisitor) {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+struct ReadOnlyVisitor;
+
+impl Visitor for ReadOnlyVisitor {
+    type Break = QueryValidationError;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+        // SELECT ... INTO creates a table
+        match has_select_into(&query.body) {
+            true => ControlFlow::Break(QueryValidationError::NotAllowed("SELECT INTO".to_string())),
+            false => ControlFlow::Continue(()),
+        }
+    }
+
+    // the visitor reaches nested statements only, e.g. WITH ... INSERT
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<Self::Break> {
+        ControlFlow::Break(QueryValidationError::NotAllowed(statement_kind(statement)))
     }
-    Ok(res)
 }
 
-pub fn query_validator(query: &str) -> bool {
-    if query.contains("select") || query.contains("SELECT") {
-        return true;
+// nested queries are visited on their own
+fn has_select_into(body: &SetExpr) -> bool {
+    match body {
+        SetExpr::Select(select) => select.into.is_some(),
+        SetExpr::SetOperation { left, right, .. } => {
+            has_select_into(left) || has_select_into(right)
+        }
+        _ => false,
+    }
+}
+
+fn statement_kind(statement: &Statement) -> String {
+    let kind = match statement {
+        Statement::Insert(_)
+        | Statement::Update { .. }
+        | Statement::Delete(_)
+        | Statement::Merge { .. }
+        | Statement::Truncate { .. } => "DML",
+        Statement::CreateTable(_)
+        | Statement::CreateView { .. }
+        | Statement::CreateSchema { .. }
+        | Statement::CreateDatabase { .. }
+        | Statement::CreateFunction(_)
+        | Statement::CreateIndex(_)
+        | Statement::AlterTable { .. }
+        | Statement::AlterView { .. }
+        | Statement::Drop { .. }
+        | Statement::DropFunction { .. } => "DDL",
+        Statement::Copy { .. } => "COPY",
+        Statement::SetVariable { .. }
+        | Statement::SetTimeZone { .. }
+        | Statement::SetNames { .. }
+        | Statement::SetNamesDefault { .. }
+        | Statement::SetRole { .. }
+        | Statement::SetTransaction { .. } => "SET",
+        Statement::StartTransaction { .. }
+        | Statement::Commit { .. }
+        | Statement::Rollback { .. } => "Transaction",
+        Statement::Prepare { .. } | Statement::Execute { .. } | Statement::Deallocate { .. } => {
+            "Prepared"
+        }
+        // the leading keyword, e.g. SHOW or GRANT
+        statement => {
+            let sql = statement.to_string();
+            return sql
+                .split_whitespace()
+                .next()
+                .unwrap_or("Other")
+                .to_uppercase();
+        }
+    };
+    kind.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::datasource::MemTable;
+
+    use super::*;
+
+    fn ctx() -> SessionContext {
+        let ctx = SessionContext::new();
+        let table = MemTable::try_new(Arc::new(LoggingTable::schema()), vec![vec![]]).unwrap();
+        ctx.register_table("logs", Arc::new(table)).unwrap();
+        ctx
     }
-    false
-}
\ No newline at end of file
+
+    #[test]
+    fn allows_read_only_queries() {
+        let ctx = ctx();
+        for query in [
+            "SELECT * FROM logs",
+            "select message from logs where timestamp > now() limit 10;",
+            "WITH recent AS (SELECT * FROM logs) SELECT count(*) FROM recent",
+            "SELECT log_stream_name FROM logs UNION SELECT log_group_name FROM logs",
+            "SELECT 1",
+            "EXPLAIN SELECT * FROM logs",
+            "EXPLAIN ANALYZE SELECT count(*) FROM logs",
+            "EXPLAIN VERBOSE SELECT * FROM logs",
+        ] {
+            assert!(query_validator(&ctx, query).is_ok(), "{query}");
+        }
+    }
+
+    #[test]
+    fn rejects_other_statements() {
+        let ctx = ctx();
+        for (query, kind) in [
+            ("INSERT INTO logs VALUES (1)", "DML"),
+            ("DELETE FROM logs", "DML"),
+            ("UPDATE logs SET message = ''", "DML"),
+            ("CREATE TABLE t AS SELECT * FROM logs", "DDL"),
+            ("CREATE VIEW v AS SELECT * FROM logs", "DDL"),
+            ("DROP TABLE logs", "DDL"),
+            (
+                "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION '/etc/passwd'",
+                "DDL",
+            ),
+            ("COPY logs TO '/tmp/logs.csv'", "COPY"),
+            ("SET datafusion.execution.batch_size = 1", "SET"),
+            ("SELECT * INTO t FROM logs", "SELECT INTO"),
+            ("EXPLAIN DROP TABLE logs", "DDL"),
+            ("EXPLAIN COPY logs TO '/tmp/logs.csv'", "COPY"),
+            (
+                "WITH a AS (SELECT 1) INSERT INTO logs SELECT * FROM a",
+                "DML",
+            ),
+            ("WITH a AS (SELECT 1) UPDATE logs SET message = ''", "DML"),
+            ("SELECT 1 UNION SELECT * INTO t FROM logs", "SELECT INTO"),
+            ("(SELECT * INTO t FROM logs)", "SELECT INTO"),
+            (
+                "WITH a AS (SELECT * INTO t FROM logs) SELECT 1",
+                "SELECT INTO",
+            ),
+            ("SELECT * FROM (SELECT * INTO t FROM logs)", "SELECT INTO"),
+            ("EXPLAIN SELECT * INTO t FROM logs", "SELECT INTO"),
+            ("SHOW TABLES", "SHOW"),
+            ("GRANT SELECT ON logs TO bob", "GRANT"),
+        ] {
+            match query_validator(&ctx, query) {
+                Err(QueryValidationError::NotAllowed(found)) => assert_eq!(found, kind, "{query}"),
+                res => panic!("{query}: expected NotAllowed, got {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_empty_and_multiple_statements() {
+        let ctx = ctx();
+        assert!(matches!(
+            query_validator(&ctx, ""),
+            Err(QueryValidationError::Empty)
+        ));
+        assert!(matches!(
+            query_validator(&ctx, "SELECT 1; DROP TABLE logs"),
+            Err(QueryValidationError::MultipleStatements)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_tables() {
+        let ctx = ctx();
+        for query in [
+            "SELECT * FROM other",
+            "SELECT * FROM logs JOIN other ON true",
+            "SELECT * FROM datafusion.public.other",
+        ] {
+            assert!(
+                matches!(
+                    query_validator(&ctx, query),
+                    Err(QueryValidationError::UnknownTable(_))
+                ),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn locates_parse_errors() {
+        let ctx = ctx();
+        for (query, location) in [
+            ("SELECT * FORM logs", "Line: 1, Column: 10"),
+            ("SELECT *\nFROM", "Line: 2, Column: 5"),
+        ] {
+            match query_validator(&ctx, query) {
+                Err(QueryValidationError::ParseError(e)) => {
+                    assert!(e.to_string().contains(location), "{query}: {e}")
+                }
+                res => panic!("{query}: expected a parse error, got {res:?}"),
+            }
+        }
+    }
+}
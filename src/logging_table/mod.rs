@@ -1,4 +1,17 @@
+mod cache;
+mod checkpoint;
+mod cloudwatch_table;
 pub mod error;
+mod ingestion;
+#[allow(clippy::module_inception)]
 mod logging_table;
+mod refresh;
+mod status;
 
+pub use cache::*;
+pub use checkpoint::*;
+pub use cloudwatch_table::*;
+pub use ingestion::*;
 pub use logging_table::*;
+pub use refresh::*;
+pub use status::*;
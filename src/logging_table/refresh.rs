@@ -0,0 +1,763 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use datafusion::{
+    arrow::{
+        array::{AsArray, RecordBatch},
+        compute::concat_batches,
+        datatypes::{Int64Type, TimestampMillisecondType},
+    },
+    datasource::{MemTable, TableProvider},
+    functions_aggregate::expr_fn::{count, max},
+    prelude::*,
+};
+use tokio::{sync::Mutex, task::JoinHandle, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::error::LoggingTableError;
+use super::{
+    process_log_groups, Checkpoints, IngestionFilter, IngestionOptions, LogCache,
+    LogGroupIngestion, LoggingTable, TableMode, TableStatuses,
+};
+use crate::log_source::LogSource;
+use crate::utils::{
+    datafusion::{register_logging_table, register_refreshable_table},
+    metrics::metrics,
+    tracing::{error_chain, log_error_chain},
+};
+
+/// Ingests the events added to the log groups since the previous pass and appends them
+/// to the registered logging table. Checkpoints are kept in memory, or next to the
+/// cache so that they survive restarts.
+#[derive(Clone)]
+pub struct LoggingTableRefresher {
+    ctx: SessionContext,
+    source: Arc<dyn LogSource>,
+    log_group_names: Vec<String>,
+    table_name: String,
+    options: IngestionOptions,
+    cache: Option<LogCache>,
+    // only bounds the in-memory table, the cache keeps every event
+    max_rows: Option<usize>,
+    state: Arc<Mutex<RefreshState>>,
+    shutdown: CancellationToken,
+    statuses: TableStatuses,
+}
+
+#[derive(Default)]
+struct RefreshState {
+    // None until loaded on the first refresh
+    checkpoints: Option<Checkpoints>,
+    // content of the in-memory table, only used without a cache
+    batches: Vec<RecordBatch>,
+}
+
+impl LoggingTableRefresher {
+    pub fn new(
+        ctx: SessionContext,
+        source: Arc<dyn LogSource>,
+        log_group_names: &[String],
+        table_name: &str,
+        options: IngestionOptions,
+    ) -> Self {
+        Self {
+            ctx,
+            source,
+            log_group_names: log_group_names.to_vec(),
+            table_name: table_name.to_string(),
+            options,
+            cache: None,
+            max_rows: None,
+            state: Arc::new(Mutex::new(RefreshState::default())),
+            shutdown: CancellationToken::new(),
+            statuses: TableStatuses::default(),
+        }
+    }
+
+    pub fn with_cache(mut self, cache: LogCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Keeps only the newest `max_rows` events of the in-memory table.
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// A shutdown cancels the ingestion in progress, but never the cache and checkpoint writes.
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Registers the table in `statuses` and keeps its status up to date.
+    pub fn with_statuses(mut self, statuses: TableStatuses) -> Self {
+        statuses.register(&self.table_name, TableMode::Snapshot);
+        self.statuses = statuses;
+        self
+    }
+
+    /// Fails only when every log group failed, otherwise the new events of the groups
+    /// that succeeded are appended to the table and the per group errors are logged.
+    pub async fn refresh(&self) -> Result<Vec<LogGroupIngestion>, LoggingTableError> {
+        let res = self.refresh_table().await;
+        match &res {
+            Ok(_) => {
+                let now = Utc::now();
+                self.statuses
+                    .update(&self.table_name, |status| status.last_refresh = Some(now));
+                metrics()
+                    .table_last_refresh
+                    .set(&[&self.table_name], now.timestamp_millis() as f64 / 1000.0);
+                // the counts are informative, failing to get them does not fail the refresh
+                match self.count().await {
+                    Ok((rows, streams, newest_event)) => {
+                        self.statuses.update(&self.table_name, |status| {
+                            status.rows = Some(rows);
+                            status.streams = Some(streams);
+                            status.newest_event = newest_event;
+                        })
+                    }
+                    Err(e) => log_error_chain(&e),
+                }
+            }
+            // the errors of the log groups were recorded already
+            Err(LoggingTableError::AllLogGroupsFailed | LoggingTableError::Cancelled) => {}
+            Err(e) => self.statuses.record_error(&self.table_name, error_chain(e)),
+        }
+        res
+    }
+
+    async fn refresh_table(&self) -> Result<Vec<LogGroupIngestion>, LoggingTableError> {
+        // held for the whole refresh so two refreshes never ingest the same events
+        let mut state = self.state.lock().await;
+        let checkpoints = match state.checkpoints.take() {
+            Some(checkpoints) => checkpoints,
+            None => match &self.cache {
+                Some(cache) => cache.read_checkpoints().await?,
+                None => Checkpoints::default(),
+            },
+        };
+        state.checkpoints = Some(checkpoints.clone());
+
+        // nothing is written before the ingestion completes, so it can be dropped
+        let (records, groups) = tokio::select! {
+            res = self.ingest(&checkpoints) => res?,
+            _ = self.shutdown.cancelled() => return Err(LoggingTableError::Cancelled),
+        };
+        for group in &groups {
+            match &group.result {
+                Ok(summary) => {
+                    tracing::info!(
+                        log_group_name = group.log_group_name,
+                        pages_visited = summary.pages_visited,
+                        streams_visited = summary.streams_visited,
+                        streams_skipped = summary.streams_skipped,
+                        events_ingested = summary.events_ingested,
+                        bytes_ingested = summary.bytes_ingested,
+                        "log group ingested"
+                    );
+                    // counted here rather than in process_log_groups, lazy queries fetch too
+                    metrics()
+                        .ingested_events
+                        .inc_by(&[&group.log_group_name], summary.events_ingested as u64);
+                    metrics()
+                        .ingested_bytes
+                        .inc_by(&[&group.log_group_name], summary.bytes_ingested as u64);
+                    // the other streams of the group were ingested
+                    for (log_stream_name, error) in &summary.failed_streams {
+                        self.statuses.record_error(
+                            &self.table_name,
+                            format!("{}/{log_stream_name}: {error}", group.log_group_name),
+                        );
+                    }
+                    if summary.is_partial() {
+                        tracing::warn!(
+                            log_group_name = group.log_group_name,
+                            max_streams_reached = summary.max_streams_reached,
+                            truncated_streams = ?summary.truncated_streams,
+                            "ingestion limits reached, logs table is partial"
+                        );
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(
+                        log_group_name = group.log_group_name,
+                        "log group ingestion failed"
+                    );
+                    log_error_chain(e);
+                    self.statuses.record_error(
+                        &self.table_name,
+                        format!("{}: {}", group.log_group_name, error_chain(e)),
+                    );
+                }
+            }
+        }
+
+        // the events of the groups that succeeded are kept even if others failed
+        let mut checkpoints = checkpoints;
+        for group in &groups {
+            if let Ok(summary) = &group.result {
+                checkpoints.update(&group.log_group_name, summary.checkpoints.clone());
+            }
+        }
+        match &self.cache {
+            Some(cache) => {
+                let files = cache.write(&records).await?;
+                cache.write_checkpoints(&mut checkpoints, &files).await?;
+                tracing::info!(files = files.len(), "log cache updated");
+            }
+            None => {
+                let batch = LoggingTable::to_record_batch(&records)?;
+                if batch.num_rows() > 0 || state.batches.is_empty() {
+                    state.batches.push(batch);
+                }
+                if let Some(max_rows) = self.max_rows {
+                    let batches = std::mem::take(&mut state.batches);
+                    state.batches = self.retain_newest(batches, max_rows).await?;
+                }
+            }
+        }
+        state.checkpoints = Some(checkpoints);
+        if groups.iter().all(|group| group.result.is_err()) {
+            return Err(LoggingTableError::AllLogGroupsFailed);
+        }
+
+        match &self.cache {
+            Some(cache) => {
+                register_refreshable_table(&self.ctx, cache.table().await?, &self.table_name)
+                    .await?
+            }
+            None => {
+                let memory_bytes: usize = state
+                    .batches
+                    .iter()
+                    .map(|batch| batch.get_array_memory_size())
+                    .sum();
+                metrics()
+                    .table_memory_bytes
+                    .set(&[&self.table_name], memory_bytes as f64);
+                let df = self.ctx.read_batches(state.batches.clone())?;
+                register_logging_table(&self.ctx, df.logical_plan().clone(), &self.table_name)
+                    .await?
+            }
+        }
+        Ok(groups)
+    }
+
+    // streams resume from their checkpoint
+    async fn ingest(
+        &self,
+        checkpoints: &Checkpoints,
+    ) -> Result<(Vec<LoggingTable>, Vec<LogGroupIngestion>), LoggingTableError> {
+        let ingestions = self.log_group_names.iter().map(|log_group_name| {
+            let filter = IngestionFilter {
+                checkpoints: Arc::new(checkpoints.log_group(log_group_name)),
+                ..Default::default()
+            };
+            async move {
+                process_log_groups(
+                    self.source.clone(),
+                    std::slice::from_ref(log_group_name),
+                    &self.options,
+                    &filter,
+                    None,
+                )
+                .await
+            }
+        });
+
+        let mut records = vec![];
+        let mut groups = vec![];
+        for (group_records, group) in futures_util::future::join_all(ingestions).await {
+            records.extend(group_records);
+            groups.extend(group);
+        }
+        Ok((records, groups))
+    }
+
+    // compacts the batches into one holding the newest `max_rows` events once they hold more
+    async fn retain_newest(
+        &self,
+        batches: Vec<RecordBatch>,
+        max_rows: usize,
+    ) -> Result<Vec<RecordBatch>, LoggingTableError> {
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        if rows <= max_rows {
+            return Ok(batches);
+        }
+        let newest = self
+            .ctx
+            .read_batches(batches)?
+            .sort(vec![col("timestamp").sort(false, false)])?
+            .limit(0, Some(max_rows))?
+            .collect()
+            .await?;
+        tracing::info!(
+            table_name = self.table_name,
+            dropped_rows = rows - max_rows,
+            "oldest events dropped from the in-memory table"
+        );
+        Ok(vec![concat_batches(
+            &Arc::new(LoggingTable::schema()),
+            &newest,
+        )?])
+    }
+
+    // rows, log streams and newest event of the registered table
+    async fn count(&self) -> Result<(usize, usize, Option<DateTime<Utc>>), LoggingTableError> {
+        let batches = self
+            .ctx
+            .table(self.table_name.as_str())
+            .await?
+            .aggregate(
+                vec![col("log_group_name"), col("log_stream_name")],
+                vec![count(lit(1)), max(col("timestamp"))],
+            )?
+            .collect()
+            .await?;
+        let mut rows = 0;
+        let mut streams = 0;
+        let mut newest_event = None;
+        for batch in batches {
+            streams += batch.num_rows();
+            rows += batch
+                .column(2)
+                .as_primitive::<Int64Type>()
+                .iter()
+                .flatten()
+                .sum::<i64>() as usize;
+            let timestamps = batch.column(3).as_primitive::<TimestampMillisecondType>();
+            newest_event = newest_event.max(timestamps.iter().flatten().max());
+        }
+        Ok((
+            rows,
+            streams,
+            newest_event.and_then(DateTime::from_timestamp_millis),
+        ))
+    }
+
+    /// Registers the table ahead of the first refresh, with the cached events or empty.
+    pub async fn register(&self) -> Result<(), LoggingTableError> {
+        let provider: Arc<dyn TableProvider> = match &self.cache {
+            Some(cache) => {
+                // the uncommitted files are deleted before the table can scan them
+                let mut state = self.state.lock().await;
+                if state.checkpoints.is_none() {
+                    state.checkpoints = Some(cache.read_checkpoints().await?);
+                }
+                cache.table().await?
+            }
+            None => Arc::new(MemTable::try_new(
+                Arc::new(LoggingTable::schema()),
+                vec![vec![]],
+            )?),
+        };
+        register_refreshable_table(&self.ctx, provider, &self.table_name).await
+    }
+
+    /// Spawns a task refreshing the table right away, then every `interval` if any. Failures
+    /// are logged and recorded in the statuses, the next refresh retries.
+    /// The task ends on shutdown, once the refresh in progress, if any, is written.
+    pub fn spawn(self, interval: Option<Duration>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = interval.map(|interval| {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                ticker
+            });
+            loop {
+                match self.refresh().await {
+                    Ok(_) => {}
+                    Err(LoggingTableError::Cancelled) => break,
+                    Err(e) => log_error_chain(&e),
+                }
+                let Some(ticker) = &mut ticker else {
+                    break;
+                };
+                tokio::select! {
+                    biased;
+                    _ = self.shutdown.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+            }
+            tracing::info!(
+                table_name = self.table_name,
+                "logging table refresh stopped"
+            );
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use async_trait::async_trait;
+    use color_eyre::eyre::eyre;
+    use datafusion::arrow::{array::AsArray, compute::cast, datatypes::DataType};
+    use futures_util::stream::BoxStream;
+
+    use super::*;
+    use crate::log_source::{
+        LogEvent, LogEventsPage, LogEventsRequest, LogStreamInfo, LogStreamsRequest,
+        MemoryLogGroup, MemoryLogSource, MemoryLogStream,
+    };
+    use crate::utils::datafusion::register_cloudwatch_table;
+
+    const GROUP: &str = "/aws/lambda/api";
+
+    // rejects the forward tokens of other generations, as CloudWatch rejects expired ones,
+    // and fails every request for `failing_stream`
+    struct FlakyLogSource {
+        inner: Arc<dyn LogSource>,
+        generation: u32,
+        failing_stream: Option<String>,
+    }
+
+    fn expiring(inner: Arc<dyn LogSource>, generation: u32) -> Arc<dyn LogSource> {
+        Arc::new(FlakyLogSource {
+            inner,
+            generation,
+            failing_stream: None,
+        })
+    }
+
+    #[async_trait]
+    impl LogSource for FlakyLogSource {
+        fn describe_log_streams(
+            &self,
+            request: LogStreamsRequest,
+        ) -> BoxStream<'static, Result<Vec<LogStreamInfo>, LoggingTableError>> {
+            self.inner.describe_log_streams(request)
+        }
+
+        async fn get_log_events(
+            &self,
+            mut request: LogEventsRequest,
+        ) -> Result<LogEventsPage, LoggingTableError> {
+            if self.failing_stream.as_ref() == Some(&request.log_stream_name) {
+                return Err(LoggingTableError::UnexpectedError(eyre!(
+                    "connection reset"
+                )));
+            }
+            let prefix = format!("{}:", self.generation);
+            if let Some(token) = request.next_token.take() {
+                let inner = token
+                    .strip_prefix(&prefix)
+                    .ok_or_else(|| LoggingTableError::InvalidNextToken(token.clone()))?;
+                request.next_token = Some(inner.to_string());
+            }
+            let mut page = self.inner.get_log_events(request).await?;
+            page.next_forward_token = page.next_forward_token.map(|x| format!("{prefix}{x}"));
+            Ok(page)
+        }
+    }
+
+    // one stream holding the first `count` of the events 0, 1, 2, ...
+    fn source(count: i64) -> Arc<dyn LogSource> {
+        source_with(
+            (0..count)
+                .map(|i| event(1_735_689_600_000 + i * 1000, &format!("event {i}")))
+                .collect(),
+        )
+    }
+
+    fn event(timestamp: i64, message: &str) -> LogEvent {
+        LogEvent {
+            timestamp: Some(timestamp),
+            message: Some(message.to_string()),
+            ingestion_time: Some(timestamp + 500),
+        }
+    }
+
+    fn source_with(events: Vec<LogEvent>) -> Arc<dyn LogSource> {
+        source_named(GROUP, events)
+    }
+
+    fn source_named(log_group_name: &str, events: Vec<LogEvent>) -> Arc<dyn LogSource> {
+        let stream = MemoryLogStream {
+            info: LogStreamInfo {
+                log_stream_name: Some("stream".to_string()),
+                last_ingestion_time: events.iter().filter_map(|x| x.ingestion_time).max(),
+                ..Default::default()
+            },
+            events,
+        };
+        Arc::new(MemoryLogSource::new(vec![MemoryLogGroup {
+            log_group_name: log_group_name.to_string(),
+            log_streams: vec![stream],
+        }]))
+    }
+
+    fn refresher(source: Arc<dyn LogSource>, options: IngestionOptions) -> LoggingTableRefresher {
+        LoggingTableRefresher::new(
+            SessionContext::new(),
+            source,
+            &[GROUP.to_string()],
+            "logs",
+            options,
+        )
+    }
+
+    async fn messages(refresher: &LoggingTableRefresher) -> Vec<String> {
+        let batches = refresher
+            .ctx
+            .sql("SELECT message FROM logs ORDER BY timestamp, message")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let messages = cast(batch.column(0), &DataType::Utf8).unwrap();
+                messages
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|x| x.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn expected(count: i64) -> Vec<String> {
+        (0..count).map(|i| format!("event {i}")).collect()
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("log-cache-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn unchanged_streams_are_skipped() {
+        let refresher = refresher(source(3), IngestionOptions::default());
+        refresher.refresh().await.unwrap();
+        let groups = refresher.refresh().await.unwrap();
+        let summary = groups[0].result.as_ref().unwrap();
+        assert_eq!(summary.streams_skipped, 1);
+        assert_eq!(summary.events_ingested, 0);
+        assert_eq!(messages(&refresher).await, expected(3));
+    }
+
+    #[tokio::test]
+    async fn truncated_streams_resume_where_they_stopped() {
+        let options = IngestionOptions {
+            max_events_per_stream: Some(2),
+            ..Default::default()
+        };
+        let refresher = refresher(source(5), options);
+        for count in [2, 4, 5, 5] {
+            refresher.refresh().await.unwrap();
+            assert_eq!(messages(&refresher).await, expected(count));
+        }
+    }
+
+    #[tokio::test]
+    async fn restarts_resume_from_the_cached_checkpoints() {
+        let dir = temp_dir();
+        let first =
+            refresher(source(3), IngestionOptions::default()).with_cache(LogCache::new(&dir));
+        first.refresh().await.unwrap();
+        assert_eq!(messages(&first).await, expected(3));
+
+        // a new process, the stream got two more events
+        let second =
+            refresher(source(5), IngestionOptions::default()).with_cache(LogCache::new(&dir));
+        second.register().await.unwrap();
+        assert_eq!(messages(&second).await, expected(3));
+        let groups = second.refresh().await.unwrap();
+        assert_eq!(groups[0].result.as_ref().unwrap().events_ingested, 2);
+        assert_eq!(messages(&second).await, expected(5));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uncommitted_cache_files_are_deleted() {
+        let dir = temp_dir();
+        let cache = LogCache::new(&dir);
+        let first = refresher(source(3), IngestionOptions::default()).with_cache(cache.clone());
+        first.refresh().await.unwrap();
+
+        // an ingestion that stopped between its cache file and its checkpoints
+        let record = LoggingTable {
+            log_group_name: Some(GROUP.to_string()),
+            log_stream_name: Some("stream".to_string()),
+            log_creation_time: None,
+            first_event_timestamp: None,
+            last_event_timestamp: None,
+            last_ingestion_time: None,
+            timestamp: Some(1_735_689_603_000),
+            message: Some("event 3".to_string()),
+            ingestion_time: Some(1_735_689_603_500),
+        };
+        let orphans = cache.write(&[record]).await.unwrap();
+
+        let second = refresher(source(5), IngestionOptions::default()).with_cache(cache);
+        second.register().await.unwrap();
+        assert!(!orphans[0].exists());
+        assert_eq!(messages(&second).await, expected(3));
+        second.refresh().await.unwrap();
+        assert_eq!(messages(&second).await, expected(5));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn caches_without_checkpoints_start_empty() {
+        let dir = temp_dir();
+        let first =
+            refresher(source(3), IngestionOptions::default()).with_cache(LogCache::new(&dir));
+        first.refresh().await.unwrap();
+        std::fs::remove_file(dir.join("checkpoints.json")).unwrap();
+
+        let second =
+            refresher(source(3), IngestionOptions::default()).with_cache(LogCache::new(&dir));
+        second.register().await.unwrap();
+        assert!(messages(&second).await.is_empty());
+        second.refresh().await.unwrap();
+        assert_eq!(messages(&second).await, expected(3));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_resume_at_the_last_ingested_event() {
+        let dir = temp_dir();
+        let first = refresher(expiring(source(3), 1), IngestionOptions::default())
+            .with_cache(LogCache::new(&dir));
+        first.refresh().await.unwrap();
+        assert_eq!(messages(&first).await, expected(3));
+
+        // a day later the cached tokens expired, "event 2b" shares the millisecond of "event 2"
+        let mut events: Vec<_> = (0..4)
+            .map(|i| event(1_735_689_600_000 + i * 1000, &format!("event {i}")))
+            .collect();
+        events.insert(3, event(1_735_689_602_000, "event 2b"));
+        let second = refresher(
+            expiring(source_with(events), 2),
+            IngestionOptions::default(),
+        )
+        .with_cache(LogCache::new(&dir));
+        let groups = second.refresh().await.unwrap();
+        let summary = groups[0].result.as_ref().unwrap();
+        assert_eq!(summary.events_ingested, 2);
+        assert!(summary.failed_streams.is_empty());
+        assert_eq!(
+            messages(&second).await,
+            ["event 0", "event 1", "event 2", "event 2b", "event 3"]
+        );
+
+        // the new checkpoint holds a token of the current generation
+        let checkpoints = second.state.lock().await.checkpoints.clone().unwrap();
+        let checkpoint = &checkpoints.log_group(GROUP)["stream"];
+        assert!(checkpoint
+            .next_forward_token
+            .as_ref()
+            .unwrap()
+            .starts_with("2:"));
+        assert_eq!(checkpoint.last_timestamp, Some(1_735_689_603_000));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_streams_leave_the_log_group_ingested() {
+        let stream = |name: &str, events: Vec<LogEvent>| MemoryLogStream {
+            info: LogStreamInfo {
+                log_stream_name: Some(name.to_string()),
+                last_ingestion_time: Some(1),
+                ..Default::default()
+            },
+            events,
+        };
+        let inner = Arc::new(MemoryLogSource::new(vec![MemoryLogGroup {
+            log_group_name: GROUP.to_string(),
+            log_streams: vec![
+                stream("stream", vec![event(1000, "a"), event(2000, "b")]),
+                stream("broken", vec![event(1500, "c")]),
+            ],
+        }]));
+        let source = Arc::new(FlakyLogSource {
+            inner,
+            generation: 1,
+            failing_stream: Some("broken".to_string()),
+        });
+        let statuses = TableStatuses::default();
+        let refresher =
+            refresher(source, IngestionOptions::default()).with_statuses(statuses.clone());
+
+        let groups = refresher.refresh().await.unwrap();
+        let summary = groups[0].result.as_ref().unwrap();
+        assert!(summary.failed_streams["broken"].contains("connection reset"));
+        assert!(!summary.checkpoints.contains_key("broken"));
+        assert_eq!(messages(&refresher).await, ["a", "b"]);
+        let status = &statuses.snapshot()["logs"];
+        assert!(status.last_refresh.is_some());
+        assert!(status.last_error.as_ref().unwrap().contains("broken"));
+    }
+
+    #[tokio::test]
+    async fn in_memory_tables_keep_the_newest_rows() {
+        let options = IngestionOptions {
+            max_events_per_stream: Some(2),
+            ..Default::default()
+        };
+        let refresher = refresher(source(5), options).with_max_rows(3);
+        for expected in [
+            vec!["event 0", "event 1"],
+            vec!["event 1", "event 2", "event 3"],
+            vec!["event 2", "event 3", "event 4"],
+        ] {
+            refresher.refresh().await.unwrap();
+            assert_eq!(messages(&refresher).await, expected);
+        }
+        // compacted into a single batch
+        assert_eq!(refresher.state.lock().await.batches.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn only_refreshes_count_as_ingested() {
+        let refreshed = format!("/test/refreshed-{}", uuid::Uuid::new_v4());
+        let lazy = format!("/test/lazy-{}", uuid::Uuid::new_v4());
+        let events = vec![event(1000, "a"), event(2000, "b")];
+
+        let refresher = LoggingTableRefresher::new(
+            SessionContext::new(),
+            source_named(&refreshed, events.clone()),
+            std::slice::from_ref(&refreshed),
+            "logs",
+            IngestionOptions::default(),
+        );
+        refresher.refresh().await.unwrap();
+
+        let ctx = SessionContext::new();
+        register_cloudwatch_table(
+            &ctx,
+            source_named(&lazy, events),
+            std::slice::from_ref(&lazy),
+            IngestionOptions::default(),
+            "logs",
+        )
+        .unwrap();
+        let batches = ctx
+            .sql("SELECT * FROM logs")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|x| x.num_rows()).sum::<usize>(), 2);
+
+        let encoded = metrics().encode();
+        let series =
+            format!("cloudwatch_viewer_ingested_events_total{{log_group=\"{refreshed}\"}} 2");
+        assert!(encoded.contains(&series), "{encoded}");
+        assert!(!encoded.contains(&lazy), "{encoded}");
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::TableMode;
+
+/// Ingestion state of a registered table.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableStatus {
+    pub mode: TableMode,
+    // rows, streams and newest_event are counted after every refresh, unknown in lazy mode
+    pub rows: Option<usize>,
+    pub streams: Option<usize>,
+    pub newest_event: Option<DateTime<Utc>>,
+    // last refresh that ingested at least one log group
+    pub last_refresh: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+/// Status of every registered table, shared by the refreshers and the `/ready` route.
+#[derive(Debug, Clone, Default)]
+pub struct TableStatuses {
+    inner: Arc<RwLock<BTreeMap<String, TableStatus>>>,
+}
+
+impl TableStatus {
+    fn new(mode: TableMode) -> Self {
+        Self {
+            mode,
+            rows: None,
+            streams: None,
+            newest_event: None,
+            last_refresh: None,
+            last_error: None,
+            last_error_at: None,
+        }
+    }
+
+    /// Lazy tables are fetched at query time and ready right away.
+    pub fn is_ready(&self) -> bool {
+        self.mode == TableMode::Lazy || self.last_refresh.is_some()
+    }
+}
+
+impl TableStatuses {
+    pub fn register(&self, table_name: &str, mode: TableMode) {
+        self.inner
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(table_name.to_string(), TableStatus::new(mode));
+    }
+
+    /// Updates a registered table, unknown tables are ignored.
+    pub fn update(&self, table_name: &str, f: impl FnOnce(&mut TableStatus)) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(status) = inner.get_mut(table_name) {
+            f(status);
+        }
+    }
+
+    pub fn record_error(&self, table_name: &str, message: String) {
+        self.update(table_name, |status| {
+            status.last_error = Some(message);
+            status.last_error_at = Some(Utc::now());
+        });
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, TableStatus> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// False until every table is ready, and while no table is registered.
+    pub fn is_ready(&self) -> bool {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        !inner.is_empty() && inner.values().all(TableStatus::is_ready)
+    }
+}
@@ -1,35 +1,105 @@
+use std::sync::Arc;
+
 use cloudwatch_viewer_web_api::{
     app_state::AppState,
-    logging_table::{process_logging_table, LoggingTable},
+    config::{Cli, Config},
+    log_source::{AwsLogSource, LogSource, MemoryLogSource},
+    logging_table::{
+        IngestionOptions, LogCache, LoggingTableRefresher, TableMode, TableStatuses,
+    },
     utils::{
         aws::get_aws_client,
-        constants::{
-            prod::{self, LOGGING_TABLE_NAME, REGION},
-            LOG_GROUP_NAME_SECRET,
-        },
-        datafusion::register_logging_table,
+        datafusion::{build_query_runtime, register_cloudwatch_table},
+        json_functions::register_json_functions,
         tracing::init_tracing,
     },
     Application,
 };
 
+use clap::Parser;
 use color_eyre::Result;
 use datafusion::prelude::*;
+use dotenvy::dotenv;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
+    dotenv().ok();
     init_tracing()?;
+    let config = Arc::new(Config::load(Cli::parse())?);
 
     let ctx = SessionContext::new();
-    let client = get_aws_client(REGION.to_string()).await;
-    let records = process_logging_table(client.clone(), &LOG_GROUP_NAME_SECRET).await?;
-    let df = LoggingTable::to_df(&ctx, &records).await?;
-    register_logging_table(&ctx, df.logical_plan().clone(), LOGGING_TABLE_NAME).await?;
-    let app_state = AppState::new(ctx, client); // #TODO is client is needed in state? 
+    register_json_functions(&ctx);
+    let source: Arc<dyn LogSource> = match &config.log_source_fixture {
+        Some(file_path) => Arc::new(MemoryLogSource::from_file(file_path)?),
+        None => {
+            let client = get_aws_client(config.aws.region.clone(), config.aws.max_retries).await;
+            Arc::new(AwsLogSource::new(client))
+        }
+    };
+    let query_runtime = build_query_runtime()?;
+    let statuses = TableStatuses::default();
+    let refresher = match config.table.mode {
+        TableMode::Snapshot => {
+            let mut refresher = LoggingTableRefresher::new(
+                ctx.clone(),
+                source.clone(),
+                &config.log_group_names,
+                &config.table.name,
+                IngestionOptions::from(&config.ingestion),
+            )
+            .with_statuses(statuses.clone());
+            if let Some(dir) = config.table.cache_dir() {
+                refresher = refresher.with_cache(LogCache::new(dir));
+            }
+            if let Some(max_rows) = config.table.max_rows {
+                refresher = refresher.with_max_rows(max_rows);
+            }
+            Some(refresher)
+        }
+        TableMode::Lazy => None,
+    };
+    let mut app_state = AppState::new(
+        config.clone(),
+        ctx.clone(),
+        source.clone(),
+        query_runtime.handle().clone(),
+        statuses.clone(),
+    );
+    // refreshes requested through /refresh are in-flight requests, bounded by the grace period
+    if let Some(refresher) = &refresher {
+        app_state = app_state.with_refresher(refresher.clone());
+    }
+    // bound before the initial ingestion so that a shutdown signal can interrupt it
+    let app = Application::build(&config, app_state).await?;
+
+    let mut refresh_task = None;
+    match refresher {
+        Some(refresher) => {
+            let refresher = refresher.with_shutdown(app.shutdown_handle());
+            refresher.register().await?;
+            // the server answers during the first ingestion, /ready reports 503 until it succeeds
+            refresh_task = Some(refresher.spawn(config.table.refresh_interval()));
+        }
+        None => {
+            register_cloudwatch_table(
+                &ctx,
+                source.clone(),
+                &config.log_group_names,
+                IngestionOptions::from(&config.ingestion),
+                &config.table.name,
+            )?;
+            statuses.register(&config.table.name, TableMode::Lazy);
+        }
+    }
 
-    let app = Application::build(prod::APP_ADDRESS, app_state).await?;
-    app.run().await?;
+    let res = app.run().await;
+    // lets the refresh in progress finish writing the cache and the checkpoints
+    if let Some(refresh_task) = refresh_task {
+        refresh_task.await?;
+    }
+    query_runtime.shutdown_background();
+    res?;
 
     Ok(())
 }
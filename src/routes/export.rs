@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use datafusion::{
+    parquet::basic::{Compression, GzipLevel, ZstdLevel},
+    prelude::SessionContext,
+    sql::parser::Statement,
+};
+use serde::{Deserialize, Serialize};
+use tokio_util::task::AbortOnDropHandle;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use super::query::{start_stream, QueryGuard};
+use crate::app_state::AppState;
+use crate::logging_table::query_validator;
+use crate::utils::datafusion::{write_parquet_files, ExportedFile, ParquetExportOptions};
+use crate::ApiError;
+
+#[derive(Deserialize)]
+pub struct ExportRequest {
+    pub query: Option<String>,
+    // rows per Parquet row group
+    pub row_group_size: Option<usize>,
+    #[serde(default)]
+    pub compression: ExportCompression,
+    // a new file is started after this many rows
+    pub max_rows_per_file: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportCompression {
+    Uncompressed,
+    #[default]
+    Snappy,
+    Gzip,
+    Lz4,
+    Zstd,
+}
+
+impl From<ExportCompression> for Compression {
+    fn from(compression: ExportCompression) -> Self {
+        match compression {
+            ExportCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ExportCompression::Snappy => Compression::SNAPPY,
+            ExportCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+            ExportCompression::Lz4 => Compression::LZ4_RAW,
+            ExportCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportResponse {
+    pub message: String,
+    pub export_id: String,
+    pub files: Vec<ExportedFile>,
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+pub async fn post_export(
+    State(state): State<AppState>,
+    Json(input): Json<ExportRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let query = input.query.ok_or(ApiError::IncorrectQuery)?;
+    let statement = query_validator(&state.ctx, &query)?;
+    let options = ParquetExportOptions {
+        row_group_size: input
+            .row_group_size
+            .unwrap_or(state.config.export.row_group_size),
+        compression: input.compression.into(),
+        max_rows_per_file: input
+            .max_rows_per_file
+            .unwrap_or(state.config.export.max_rows_per_file),
+    };
+    if options.row_group_size == 0 || options.max_rows_per_file == 0 {
+        return Err(ApiError::InvalidExportOptions(
+            "row_group_size and max_rows_per_file must be positive".to_string(),
+        ));
+    }
+
+    // each export gets its own directory, removed again if the export does not complete
+    let export_id = Uuid::new_v4().to_string();
+    let dir = state.config.export.dir.join(&export_id);
+    let guard = QueryGuard::new();
+    let task = AbortOnDropHandle::new(state.query_runtime.spawn(
+        execute_export(state.ctx.clone(), statement, dir, options).instrument(guard.span()),
+    ));
+    let files = tokio::time::timeout(Duration::from_secs(state.config.export.timeout_secs), task)
+        .await
+        .map_err(|_| ApiError::QueryTimeout {
+            elapsed: guard.elapsed(),
+            client_timeout: false,
+        })?
+        .map_err(|e| ApiError::UnexpectedError(e.into()))??;
+    guard.finish();
+
+    let response = ExportResponse {
+        message: "Query exported".to_string(),
+        export_id,
+        rows: files.iter().map(|file| file.rows).sum(),
+        bytes: files.iter().map(|file| file.bytes).sum(),
+        files,
+    };
+    Ok((StatusCode::OK, Json(response)))
+}
+
+async fn execute_export(
+    ctx: SessionContext,
+    statement: Statement,
+    dir: PathBuf,
+    options: ParquetExportOptions,
+) -> Result<Vec<ExportedFile>, ApiError> {
+    let stream = start_stream(ctx, statement, 0, None).await?;
+    Ok(write_parquet_files(&dir, stream, &options).await?)
+}
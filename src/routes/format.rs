@@ -0,0 +1,165 @@
+use axum::http::{header::ACCEPT, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use crate::ApiError;
+
+/// Encoding of the query results, picked from the `format` field or the `Accept` header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFormat {
+    // JSON envelope with columns, content and pagination
+    #[default]
+    Json,
+    Csv,
+    Ndjson,
+    Parquet,
+    Arrow,
+    // the message column only, one line per row
+    Text,
+}
+
+impl ResultFormat {
+    // binary formats carry the column types and are never converted
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Parquet | Self::Arrow)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv",
+            Self::Ndjson => "application/x-ndjson",
+            Self::Parquet => "application/vnd.apache.parquet",
+            Self::Arrow => "application/vnd.apache.arrow.stream",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "text/csv" => Some(Self::Csv),
+            "application/x-ndjson" | "application/jsonl" => Some(Self::Ndjson),
+            "application/vnd.apache.parquet" => Some(Self::Parquet),
+            "application/vnd.apache.arrow.stream" => Some(Self::Arrow),
+            "text/plain" | "text/*" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// The `format` field wins over the `Accept` header, which defaults to JSON when missing.
+    pub fn negotiate(format: Option<Self>, headers: &HeaderMap) -> Result<Self, ApiError> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        let Some(accept) = headers.get(ACCEPT) else {
+            return Ok(Self::Json);
+        };
+        let accept = accept
+            .to_str()
+            .map_err(|_| ApiError::NotAcceptable("invalid Accept header".to_string()))?;
+
+        // media ranges by decreasing quality, ties keep the header order
+        let mut ranges: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media_type = parts.next().unwrap_or_default();
+                let quality = parts
+                    .find_map(|x| x.strip_prefix("q="))
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(1.0);
+                (media_type, quality)
+            })
+            .filter(|(media_type, quality)| !media_type.is_empty() && *quality > 0.0)
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+            .into_iter()
+            .find_map(|(media_type, _)| Self::from_media_type(&media_type.to_ascii_lowercase()))
+            .ok_or_else(|| ApiError::NotAcceptable(accept.to_string()))
+    }
+}
+
+/// How timestamp columns are written by the text formats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    // e.g. 2025-01-01T00:00:01Z
+    #[default]
+    Rfc3339,
+    // milliseconds after Jan 1, 1970 00:00:00 UTC, as before timestamps were typed
+    Epoch,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn negotiate(accept: &str) -> Result<ResultFormat, ApiError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str(accept).unwrap());
+        ResultFormat::negotiate(None, &headers)
+    }
+
+    #[test]
+    fn defaults_to_json() {
+        let format = ResultFormat::negotiate(None, &HeaderMap::new()).unwrap();
+        assert_eq!(format, ResultFormat::Json);
+        assert_eq!(negotiate("*/*").unwrap(), ResultFormat::Json);
+    }
+
+    #[test]
+    fn picks_the_media_type() {
+        for (accept, format) in [
+            ("application/json", ResultFormat::Json),
+            ("text/csv", ResultFormat::Csv),
+            ("application/x-ndjson", ResultFormat::Ndjson),
+            ("application/jsonl", ResultFormat::Ndjson),
+            ("application/vnd.apache.parquet", ResultFormat::Parquet),
+            ("application/vnd.apache.arrow.stream", ResultFormat::Arrow),
+            ("text/plain", ResultFormat::Text),
+            ("TEXT/CSV", ResultFormat::Csv),
+            ("text/csv; charset=utf-8", ResultFormat::Csv),
+        ] {
+            assert_eq!(negotiate(accept).unwrap(), format, "{accept}");
+        }
+    }
+
+    #[test]
+    fn follows_the_quality_then_the_order() {
+        for (accept, format) in [
+            ("text/csv, application/json", ResultFormat::Csv),
+            ("text/csv;q=0.5, application/json", ResultFormat::Json),
+            ("text/html, text/csv;q=0.9, */*;q=0.1", ResultFormat::Csv),
+            ("text/csv;q=0, */*;q=0.1", ResultFormat::Json),
+            ("application/xml, text/csv", ResultFormat::Csv),
+            (
+                "text/csv;q=invalid, application/json;q=0.5",
+                ResultFormat::Csv,
+            ),
+        ] {
+            assert_eq!(negotiate(accept).unwrap(), format, "{accept}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_media_types() {
+        for accept in ["application/xml", "text/csv;q=0", ""] {
+            assert!(
+                matches!(negotiate(accept), Err(ApiError::NotAcceptable(_))),
+                "{accept}"
+            );
+        }
+    }
+
+    #[test]
+    fn format_field_wins() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/xml"));
+        let format = ResultFormat::negotiate(Some(ResultFormat::Parquet), &headers).unwrap();
+        assert_eq!(format, ResultFormat::Parquet);
+    }
+}
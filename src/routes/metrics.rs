@@ -0,0 +1,18 @@
+use axum::{
+    http::{header::CONTENT_TYPE, HeaderValue},
+    response::IntoResponse,
+};
+
+use crate::utils::metrics::metrics;
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub async fn get_metrics() -> impl IntoResponse {
+    (
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static(PROMETHEUS_CONTENT_TYPE),
+        )],
+        metrics().encode(),
+    )
+}
@@ -1,5 +1,15 @@
 mod alive;
+mod export;
+mod format;
+mod metrics;
 mod query;
+mod ready;
+mod refresh;
 
 pub use alive::*;
+pub use export::*;
+pub use format::*;
+pub use metrics::*;
 pub use query::*;
+pub use ready::*;
+pub use refresh::*;
@@ -1,49 +1,611 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response as HttpResponse},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use datafusion::{
+    arrow::{array::RecordBatch, datatypes::Schema},
+    execution::{context::SQLOptions, SendableRecordBatchStream},
+    logical_expr::LogicalPlan,
+    physical_plan::collect,
+    prelude::{DataFrame, SessionContext},
+    sql::parser::Statement,
+};
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{Instrument, Span};
 
-use crate::logging_table::query_validator;
+use super::{ResultFormat, TimestampFormat};
+use crate::app_state::AppState;
+use crate::logging_table::{error::LoggingTableError, partial_scans, query_validator, PartialScan};
+use crate::utils::datafusion::{write_parquet, RefreshableTable};
+use crate::utils::metrics::metrics;
+use crate::utils::record_batch::{
+    record_batches_to_arrow, record_batches_to_columns, record_batches_to_csv,
+    record_batches_to_ndjson, record_batches_to_rows, record_batches_to_text, schema_columns,
+    timestamps_to_epoch_millis, truncate_batches, BatchEncoder, ColumnInfo,
+};
+use crate::utils::tracing::current_request_id;
 use crate::ApiError;
-use crate::{app_state::AppState, logging_table::LoggingTable};
 
 #[derive(Deserialize)]
 pub struct Request {
     pub query: Option<String>,
+    #[serde(default)]
+    pub layout: Layout,
+    // capped by query.max_rows
+    pub page_size: Option<usize>,
+    // next_cursor of the previous page, only valid for the same query
+    pub cursor: Option<String>,
+    // only honored when lower than query.timeout_secs
+    pub timeout_ms: Option<u64>,
+    // overrides the Accept header
+    pub format: Option<ResultFormat>,
+    // sends each batch as soon as it is produced, ndjson and arrow only,
+    // streamed results are not paginated, page_size and query.max_rows cap them
+    #[serde(default)]
+    pub stream: bool,
+    // how the text formats write timestamps, the binary formats keep the Arrow types
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    // one JSON object per row
+    #[default]
+    Rows,
+    // one JSON array per column, in the order of `columns`
+    Columns,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response {
     pub message: String,
-    pub content: Option<Vec<LoggingTable>>,
+    pub columns: Vec<ColumnInfo>,
+    // rows in content, 0 for an empty result
+    pub row_count: usize,
+    pub content: Option<Content>,
+    pub next_cursor: Option<String>,
+    // true when more rows are available than returned
+    pub truncated: bool,
+    // logical and physical planning, no log source is read before execution
+    pub planning_ms: u128,
+    // includes fetching the lazy tables from the log source
+    pub execution_ms: u128,
+    // the tables the query read from
+    pub tables: Vec<TableSnapshot>,
+    // log groups of the lazy tables missing events because of the ingestion limits
+    // or failing streams
+    pub partial_scans: Vec<PartialScan>,
+}
+
+/// Snapshot of a queried table, version and refreshed_at are None for the tables
+/// fetched from the log source at query time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub name: String,
+    pub version: Option<u64>,
+    pub refreshed_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Rows(Vec<Map<String, Value>>),
+    Columns(Vec<Vec<Value>>),
+}
+
+// pagination of the non JSON formats, which have no envelope
+const NEXT_CURSOR_HEADER: HeaderName = HeaderName::from_static("x-next-cursor");
+const TRUNCATED_HEADER: HeaderName = HeaderName::from_static("x-truncated");
+const PARTIAL_HEADER: HeaderName = HeaderName::from_static("x-partial");
+// encoded batches buffered ahead of the client
+const STREAM_BUFFER_BATCHES: usize = 2;
+
 pub async fn post_query(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(input): Json<Request>,
-) -> Result<impl IntoResponse, ApiError> {
-    let query = match input.query {
-        Some(v) => {
-            match query_validator(&v) {
-                true => v,
-                false => return Err(ApiError::IncorrectQuery)
-            }
-        },
-        None => return Err(ApiError::IncorrectQuery)
+) -> Result<HttpResponse, ApiError> {
+    let format = ResultFormat::negotiate(input.format, &headers)?;
+    let query = input.query.ok_or(ApiError::IncorrectQuery)?;
+    let statement = query_validator(&state.ctx, &query)?;
+    let offset = match &input.cursor {
+        Some(cursor) => QueryCursor::decode(cursor, &query)?.offset,
+        None => 0,
     };
-    let df = state
-        .ctx
-        .sql(&query)
-        .await
-        .map_err(|e| ApiError::UnexpectedError(e.into()))?;
-    let res = LoggingTable::df_to_records(df)
+    // clients can only lower the server timeout
+    let server_timeout = Duration::from_secs(state.config.query.timeout_secs);
+    let (timeout, client_timeout) = match input.timeout_ms.map(Duration::from_millis) {
+        Some(timeout) if timeout < server_timeout => (timeout, true),
+        _ => (server_timeout, false),
+    };
+    if input.stream {
+        let max_rows = state.config.query.max_rows;
+        let fetch = input.page_size.unwrap_or(max_rows).min(max_rows);
+        // the offset comes from the client, DataFusion adds the two
+        offset.checked_add(fetch).ok_or(ApiError::InvalidCursor)?;
+        return stream_query(
+            state,
+            statement,
+            format,
+            offset,
+            Some(fetch),
+            timeout,
+            client_timeout,
+            input.timestamp_format,
+        )
+        .await;
+    }
+
+    let page_size = input
+        .page_size
+        .unwrap_or(state.config.query.max_rows)
+        .clamp(1, state.config.query.max_rows);
+    // the offset comes from the client, the extra row below must fit as well
+    let next_offset = offset
+        .checked_add(page_size)
+        .filter(|next_offset| *next_offset < usize::MAX)
+        .ok_or(ApiError::InvalidCursor)?;
+
+    // the query is dropped, and with it every DataFusion task, on timeout or client disconnect
+    let tables = table_snapshots(&state.ctx, &statement).await?;
+    let guard = QueryGuard::new();
+    // one extra row tells whether there is a next page
+    // queries run on their own runtime so CPU bound planning and execution can not
+    // delay the timeout, dropping the handle aborts the task
+    let task = AbortOnDropHandle::new(state.query_runtime.spawn(
+        execute_query(state.ctx.clone(), statement, offset, page_size + 1).instrument(guard.span()),
+    ));
+    let output = tokio::time::timeout(timeout, task)
         .await
-        .map_err(|e| ApiError::UnexpectedError(e.into()))?;
-    if res.is_empty() {
-        return Err(ApiError::QueryResultIsEmpty);
+        .map_err(|_| ApiError::QueryTimeout {
+            elapsed: guard.elapsed(),
+            client_timeout,
+        })?
+        .map_err(|e| ApiError::UnexpectedError(e.into()))??;
+    guard.finish();
+
+    let num_rows: usize = output.batches.iter().map(|batch| batch.num_rows()).sum();
+    let truncated = output.paginated && num_rows > page_size;
+    let row_count = match output.paginated {
+        true => num_rows.min(page_size),
+        false => num_rows,
+    };
+    metrics().query_rows.inc_by(&[], row_count as u64);
+    let batches = truncate_batches(output.batches, row_count);
+    let next_cursor = truncated.then(|| QueryCursor::new(next_offset, &query).encode());
+    let (schema, batches) = match (input.timestamp_format, format.is_binary()) {
+        (TimestampFormat::Epoch, false) => timestamps_to_epoch_millis(&output.schema, &batches)
+            .map_err(|e| ApiError::UnexpectedError(e.into()))?,
+        _ => (output.schema, batches),
+    };
+    if format != ResultFormat::Json {
+        let body = encode_results(format, schema, batches).await?;
+        let mut response = (
+            StatusCode::OK,
+            [(
+                CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            )],
+            body,
+        )
+            .into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            TRUNCATED_HEADER,
+            HeaderValue::from_static(if truncated { "true" } else { "false" }),
+        );
+        headers.insert(
+            PARTIAL_HEADER,
+            HeaderValue::from_static(if output.partial_scans.is_empty() {
+                "false"
+            } else {
+                "true"
+            }),
+        );
+        if let Some(next_cursor) = next_cursor.and_then(|x| HeaderValue::from_str(&x).ok()) {
+            headers.insert(NEXT_CURSOR_HEADER, next_cursor);
+        }
+        return Ok(response);
     }
+    let content = match input.layout {
+        Layout::Rows => Content::Rows(
+            record_batches_to_rows(&batches).map_err(|e| ApiError::UnexpectedError(e.into()))?,
+        ),
+        Layout::Columns => Content::Columns(
+            record_batches_to_columns(&schema, &batches)
+                .map_err(|e| ApiError::UnexpectedError(e.into()))?,
+        ),
+    };
 
     let response = Response {
         message: "Table selected".to_string(),
-        content: Some(res),
+        columns: schema_columns(&schema),
+        row_count,
+        content: Some(content),
+        next_cursor,
+        truncated,
+        planning_ms: output.planning.as_millis(),
+        execution_ms: output.execution.as_millis(),
+        tables,
+        partial_scans: output.partial_scans,
+    };
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+async fn encode_results(
+    format: ResultFormat,
+    schema: Schema,
+    batches: Vec<RecordBatch>,
+) -> Result<Vec<u8>, ApiError> {
+    if format == ResultFormat::Text && schema.column_with_name("message").is_none() {
+        return Err(ApiError::UnsupportedFormat(
+            "text/plain requires a message column".to_string(),
+        ));
+    }
+    let body = match format {
+        ResultFormat::Csv => record_batches_to_csv(&batches),
+        ResultFormat::Ndjson => record_batches_to_ndjson(&batches),
+        ResultFormat::Arrow => record_batches_to_arrow(&schema, &batches),
+        ResultFormat::Text => record_batches_to_text(&batches),
+        ResultFormat::Parquet => {
+            let stream = futures_util::stream::iter(batches.into_iter().map(Ok));
+            write_parquet(Arc::new(schema), stream).await
+        }
+        ResultFormat::Json => unreachable!("JSON results are wrapped in the envelope"),
     };
-    Ok((StatusCode::OK, Json(response)))
+    body.map_err(|e| ApiError::UnexpectedError(e.into()))
+}
+
+struct QueryOutput {
+    schema: Schema,
+    batches: Vec<RecordBatch>,
+    // logical and physical planning, the lazy tables are fetched during execution
+    planning: Duration,
+    execution: Duration,
+    // false for EXPLAIN, whose plan is returned whole
+    paginated: bool,
+    // only known for the log groups read to the end
+    partial_scans: Vec<PartialScan>,
+}
+
+async fn execute_query(
+    ctx: SessionContext,
+    statement: Statement,
+    offset: usize,
+    fetch: usize,
+) -> Result<QueryOutput, ApiError> {
+    let started = Instant::now();
+    let df = plan_query(&ctx, statement, offset, Some(fetch)).await?;
+    let paginated = !is_explain(df.logical_plan());
+    let schema = Schema::from(df.schema());
+    let task_ctx = Arc::new(df.task_ctx());
+    let plan = df.create_physical_plan().await?;
+    let planning = started.elapsed();
+
+    let started = Instant::now();
+    let batches = collect(plan.clone(), task_ctx).await?;
+    Ok(QueryOutput {
+        schema,
+        batches,
+        planning,
+        execution: started.elapsed(),
+        paginated,
+        partial_scans: partial_scans(plan.as_ref()),
+    })
+}
+
+// versions are read before planning, a refresh in between is not reflected
+async fn table_snapshots(
+    ctx: &SessionContext,
+    statement: &Statement,
+) -> Result<Vec<TableSnapshot>, ApiError> {
+    let mut tables = vec![];
+    for table in ctx.state().resolve_table_references(statement)? {
+        let Ok(provider) = ctx.table_provider(table.clone()).await else {
+            continue;
+        };
+        let version = provider
+            .as_any()
+            .downcast_ref::<RefreshableTable>()
+            .map(RefreshableTable::version);
+        tables.push(TableSnapshot {
+            name: table.to_string(),
+            version: version.map(|x| x.version),
+            refreshed_at: version.map(|x| x.refreshed_at),
+        });
+    }
+    Ok(tables)
+}
+
+// the stream holds the partitions running on the query runtime, dropping it stops them
+pub(crate) async fn start_stream(
+    ctx: SessionContext,
+    statement: Statement,
+    offset: usize,
+    fetch: Option<usize>,
+) -> Result<SendableRecordBatchStream, ApiError> {
+    let df = plan_query(&ctx, statement, offset, fetch).await?;
+    Ok(df.execute_stream().await?)
+}
+
+async fn plan_query(
+    ctx: &SessionContext,
+    statement: Statement,
+    offset: usize,
+    fetch: Option<usize>,
+) -> Result<DataFrame, ApiError> {
+    let plan = ctx.state().statement_to_plan(statement).await?;
+    read_only_sql_options().verify_plan(&plan)?;
+    let df = ctx.execute_logical_plan(plan).await?;
+    // DataFusion requires EXPLAIN and EXPLAIN ANALYZE at the root of the plan
+    match is_explain(df.logical_plan()) {
+        true => Ok(df),
+        false => Ok(df.limit(offset, fetch)?),
+    }
+}
+
+fn is_explain(plan: &LogicalPlan) -> bool {
+    matches!(plan, LogicalPlan::Explain(_) | LogicalPlan::Analyze(_))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn stream_query(
+    state: AppState,
+    statement: Statement,
+    format: ResultFormat,
+    offset: usize,
+    fetch: Option<usize>,
+    timeout: Duration,
+    client_timeout: bool,
+    timestamp_format: TimestampFormat,
+) -> Result<HttpResponse, ApiError> {
+    if !matches!(format, ResultFormat::Ndjson | ResultFormat::Arrow) {
+        return Err(ApiError::UnsupportedFormat(
+            "streaming requires the ndjson or arrow format".to_string(),
+        ));
+    }
+    // the timeout covers the whole response, not only the time to the first batch
+    let guard = QueryGuard::new();
+    let deadline = tokio::time::Instant::now() + timeout;
+    let task =
+        AbortOnDropHandle::new(state.query_runtime.spawn(
+            start_stream(state.ctx.clone(), statement, offset, fetch).instrument(guard.span()),
+        ));
+    let stream = tokio::time::timeout_at(deadline, task)
+        .await
+        .map_err(|_| ApiError::QueryTimeout {
+            elapsed: guard.elapsed(),
+            client_timeout,
+        })?
+        .map_err(|e| ApiError::UnexpectedError(e.into()))??;
+    let encoder = match format {
+        ResultFormat::Arrow => BatchEncoder::arrow(&stream.schema()),
+        _ => Ok(BatchEncoder::ndjson()),
+    }
+    .map_err(|e| ApiError::UnexpectedError(e.into()))?;
+
+    // the bounded channel makes the producer wait for the client to read
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_BATCHES);
+    let epoch_millis = timestamp_format == TimestampFormat::Epoch && !format.is_binary();
+    let producer = async move {
+        let res =
+            tokio::time::timeout_at(deadline, send_batches(stream, encoder, epoch_millis, &tx))
+                .await;
+        let e = match res {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => e,
+            Err(_) => LoggingTableError::QueryTimeout(timeout),
+        };
+        tracing::warn!(error = %e, "query stream interrupted");
+        // an error ends the chunked body without its terminating chunk
+        let _ = tx.send(Err(e)).await;
+    };
+    let task = AbortOnDropHandle::new(state.query_runtime.spawn(producer.instrument(guard.span())));
+    let body = Body::from_stream(QueryStream {
+        rx,
+        _task: task,
+        guard: Some(guard),
+    });
+    let headers = [(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    )];
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+async fn send_batches(
+    mut stream: SendableRecordBatchStream,
+    mut encoder: BatchEncoder,
+    epoch_millis: bool,
+    tx: &mpsc::Sender<Result<Vec<u8>, LoggingTableError>>,
+) -> Result<(), LoggingTableError> {
+    while let Some(mut batch) = stream.next().await.transpose()? {
+        if epoch_millis {
+            let (_, mut batches) = timestamps_to_epoch_millis(&batch.schema(), &[batch])?;
+            batch = batches.remove(0);
+        }
+        // the client went away
+        if tx.send(Ok(encoder.encode(&batch)?)).await.is_err() {
+            return Ok(());
+        }
+        metrics().query_rows.inc_by(&[], batch.num_rows() as u64);
+    }
+    let _ = tx.send(Ok(encoder.finish()?)).await;
+    Ok(())
+}
+
+/// Body of a streamed query, dropping it before the end aborts the query.
+struct QueryStream {
+    rx: mpsc::Receiver<Result<Vec<u8>, LoggingTableError>>,
+    _task: AbortOnDropHandle<()>,
+    guard: Option<QueryGuard>,
+}
+
+impl Stream for QueryStream {
+    type Item = Result<Vec<u8>, LoggingTableError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.rx.poll_recv(cx));
+        if !matches!(item, Some(Ok(_))) {
+            if let Some(guard) = self.guard.take() {
+                guard.finish();
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Logs queries that were dropped before they finished, e.g. because the client disconnected.
+pub(crate) struct QueryGuard {
+    started: Instant,
+    finished: bool,
+    span: Span,
+}
+
+impl QueryGuard {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            finished: false,
+            // queries run on the query runtime, out of the request span, hence the request ID
+            span: tracing::info_span!(
+                parent: None,
+                "query",
+                request_id = current_request_id().as_deref()
+            ),
+        }
+    }
+
+    /// Span the query tasks are instrumented with.
+    pub(crate) fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub(crate) fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for QueryGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _enter = self.span.enter();
+            tracing::warn!(elapsed = ?self.elapsed(), "query cancelled");
+        }
+    }
+}
+
+// second line of defence after query_validator, rejects any plan that would mutate the context
+fn read_only_sql_options() -> SQLOptions {
+    SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false)
+        .with_allow_statements(false)
+}
+
+/// Opaque pagination cursor, the offset of the next page bound to the query text.
+/// Pages are only stable for queries with a total ORDER BY.
+#[derive(Serialize, Deserialize)]
+struct QueryCursor {
+    offset: usize,
+    query_hash: u64,
+}
+
+impl QueryCursor {
+    fn new(offset: usize, query: &str) -> Self {
+        Self {
+            offset,
+            query_hash: query_hash(query),
+        }
+    }
+
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(cursor: &str, query: &str) -> Result<Self, ApiError> {
+        let json = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| ApiError::InvalidCursor)?;
+        let cursor: Self = serde_json::from_slice(&json).map_err(|_| ApiError::InvalidCursor)?;
+        if cursor.query_hash != query_hash(query) {
+            return Err(ApiError::InvalidCursor);
+        }
+        Ok(cursor)
+    }
+}
+
+// a stable digest, cursors must survive restarts and toolchain upgrades
+fn query_hash(query: &str) -> u64 {
+    let digest = Sha256::digest(query);
+    u64::from_be_bytes(digest[..8].try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let query = "SELECT * FROM logs ORDER BY timestamp";
+        let encoded = QueryCursor::new(200, query).encode();
+        // opaque and URL safe
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(QueryCursor::decode(&encoded, query).unwrap().offset, 200);
+    }
+
+    #[test]
+    fn cursor_is_bound_to_the_query() {
+        let encoded = QueryCursor::new(200, "SELECT * FROM logs").encode();
+        assert!(matches!(
+            QueryCursor::decode(&encoded, "SELECT * FROM logs LIMIT 1"),
+            Err(ApiError::InvalidCursor)
+        ));
+    }
+
+    #[test]
+    fn query_hash_is_stable() {
+        // the first 8 bytes of the SHA-256 of the query
+        assert_eq!(query_hash("SELECT * FROM logs"), 0x805c2bf5ee23ea88);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        let query = "SELECT * FROM logs";
+        let not_json = URL_SAFE_NO_PAD.encode("offset=10");
+        let wrong_fields = URL_SAFE_NO_PAD.encode(r#"{"offset":-1,"query_hash":0}"#);
+        for cursor in ["", "not base64!", &not_json, &wrong_fields] {
+            assert!(
+                matches!(
+                    QueryCursor::decode(cursor, query),
+                    Err(ApiError::InvalidCursor)
+                ),
+                "{cursor}"
+            );
+        }
+    }
 }
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::app_state::AppState;
+use crate::logging_table::TableStatus;
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    pub tables: BTreeMap<String, TableReadiness>,
+}
+
+#[derive(Serialize)]
+pub struct TableReadiness {
+    #[serde(flatten)]
+    pub status: TableStatus,
+    // how far the newest event lags behind the current time
+    pub lag_ms: Option<i64>,
+}
+
+/// 503 until every table finished its first ingestion, the body describes the tables either way.
+pub async fn get_ready(State(state): State<AppState>) -> impl IntoResponse {
+    let now = Utc::now();
+    let tables = state
+        .statuses
+        .snapshot()
+        .into_iter()
+        .map(|(name, status)| {
+            let lag_ms = status
+                .newest_event
+                .map(|newest_event| (now - newest_event).num_milliseconds());
+            (name, TableReadiness { status, lag_ms })
+        })
+        .collect();
+    let ready = state.statuses.is_ready();
+    let status_code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status_code, Json(ReadyResponse { ready, tables }))
+}
@@ -0,0 +1,49 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::app_state::AppState;
+use crate::utils::tracing::error_chain;
+use crate::ApiError;
+
+#[derive(Serialize)]
+pub struct RefreshResponse {
+    pub log_groups: Vec<LogGroupRefresh>,
+}
+
+#[derive(Serialize)]
+pub struct LogGroupRefresh {
+    pub log_group_name: String,
+    pub events_ingested: Option<usize>,
+    pub bytes_ingested: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Refreshes the snapshot table right away instead of waiting for the next interval.
+pub async fn post_refresh(
+    State(state): State<AppState>,
+) -> Result<Json<RefreshResponse>, ApiError> {
+    let refresher = state
+        .refresher
+        .as_ref()
+        .ok_or_else(|| ApiError::NotRefreshable(state.config.table.name.clone()))?;
+    let log_groups = refresher
+        .refresh()
+        .await?
+        .into_iter()
+        .map(|group| match group.result {
+            Ok(summary) => LogGroupRefresh {
+                log_group_name: group.log_group_name,
+                events_ingested: Some(summary.events_ingested),
+                bytes_ingested: Some(summary.bytes_ingested),
+                error: None,
+            },
+            Err(e) => LogGroupRefresh {
+                log_group_name: group.log_group_name,
+                events_ingested: None,
+                bytes_ingested: None,
+                error: Some(error_chain(&e)),
+            },
+        })
+        .collect();
+    Ok(Json(RefreshResponse { log_groups }))
+}
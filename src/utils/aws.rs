@@ -1,9 +1,17 @@
 use aws_config::{retry::RetryConfig, BehaviorVersion, Region};
-use aws_sdk_cloudwatchlogs::{config::Builder, Client};
+use aws_sdk_cloudwatchlogs::{
+    config::{
+        interceptors::BeforeTransmitInterceptorContextRef, Builder, ConfigBag, Intercept,
+        RuntimeComponents,
+    },
+    error::BoxError,
+    Client,
+};
+use aws_smithy_runtime_api::client::{orchestrator::Metadata, retries::RequestAttempts};
 
-use super::constants::prod::AWS_MAX_RETRIES;
+use crate::utils::metrics::metrics;
 
-pub async fn get_aws_client(region: String) -> Client {
+pub async fn get_aws_client(region: String, max_retries: u32) -> Client {
     let region = Region::new(region);
 
     let sdk_config = aws_config::defaults(BehaviorVersion::latest())
@@ -12,9 +20,35 @@ pub async fn get_aws_client(region: String) -> Client {
         .await;
 
     let config_builder = Builder::from(&sdk_config)
-        .retry_config(RetryConfig::standard().with_max_attempts(AWS_MAX_RETRIES));
+        .retry_config(RetryConfig::standard().with_max_attempts(max_retries))
+        .interceptor(MetricsInterceptor);
 
     let config = config_builder.build();
 
     Client::from_conf(config)
 }
+
+/// Counts the API calls and their retries by operation.
+#[derive(Debug)]
+struct MetricsInterceptor;
+
+impl Intercept for MetricsInterceptor {
+    fn name(&self) -> &'static str {
+        "MetricsInterceptor"
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let operation = cfg.load::<Metadata>().map_or("unknown", |x| x.name());
+        // attempts start at 1, every later attempt is a retry
+        match cfg.load::<RequestAttempts>().map(|x| x.attempts()) {
+            Some(attempts) if attempts > 1 => metrics().cloudwatch_retries.inc(&[operation]),
+            _ => metrics().cloudwatch_calls.inc(&[operation]),
+        }
+        Ok(())
+    }
+}
@@ -1,34 +1,307 @@
-use std::sync::Arc;
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use datafusion::{
-    arrow::datatypes::Schema, datasource::ViewTable, logical_expr::LogicalPlan,
-    parquet::arrow::AsyncArrowWriter, prelude::*,
+    arrow::{
+        array::RecordBatch,
+        datatypes::{Schema, SchemaRef},
+    },
+    catalog::Session,
+    datasource::{TableProvider, TableType, ViewTable},
+    error::Result as DataFusionResult,
+    execution::SendableRecordBatchStream,
+    logical_expr::{LogicalPlan, TableProviderFilterPushDown},
+    parquet::{
+        arrow::{async_writer::AsyncFileWriter, AsyncArrowWriter},
+        basic::Compression,
+        file::properties::WriterProperties,
+    },
+    physical_plan::ExecutionPlan,
+    prelude::*,
 };
-use tokio::{fs::File, io::AsyncWriteExt};
-use tokio_stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::{fs::File, runtime::Runtime};
+use tokio_stream::{Stream, StreamExt};
 
-use crate::logging_table::error::LoggingTableError;
+use crate::log_source::LogSource;
+use crate::logging_table::{error::LoggingTableError, CloudWatchTable, IngestionOptions};
 
+/// Registers the logging table, or atomically swaps its content if it is already registered.
 pub async fn register_logging_table(
     ctx: &SessionContext,
     plan: LogicalPlan,
     table_name: &str,
 ) -> Result<(), LoggingTableError> {
-    let view = ViewTable::try_new(plan, None)?;
-    ctx.register_table(table_name, Arc::new(view))?;
+    let view = Arc::new(ViewTable::try_new(plan, None)?);
+    register_refreshable_table(ctx, view, table_name).await
+}
+
+/// Same as `register_logging_table` for any provider, e.g. the cache listing table.
+pub async fn register_refreshable_table(
+    ctx: &SessionContext,
+    provider: Arc<dyn TableProvider>,
+    table_name: &str,
+) -> Result<(), LoggingTableError> {
+    if let Ok(table) = ctx.table_provider(table_name).await {
+        if let Some(table) = table.as_any().downcast_ref::<RefreshableTable>() {
+            table.swap(provider);
+            return Ok(());
+        }
+        ctx.deregister_table(table_name)?;
+    }
+    ctx.register_table(table_name, Arc::new(RefreshableTable::new(provider)))?;
     Ok(())
 }
 
+/// Registers the logging table in lazy mode, fetching from the log source at query time.
+pub fn register_cloudwatch_table(
+    ctx: &SessionContext,
+    source: Arc<dyn LogSource>,
+    log_group_names: &[String],
+    options: IngestionOptions,
+    table_name: &str,
+) -> Result<(), LoggingTableError> {
+    let table = CloudWatchTable::new(source, log_group_names, options);
+    ctx.register_table(table_name, Arc::new(table))?;
+    Ok(())
+}
+
+/// Table whose content can be replaced while queries are running.
+/// Each scan captures the provider that is current at planning time,
+/// so a running query keeps reading the same snapshot after a swap.
+#[derive(Debug)]
+pub struct RefreshableTable {
+    inner: RwLock<(Arc<dyn TableProvider>, TableVersion)>,
+}
+
+/// Snapshot of a refreshable table, the version starts at 1 and grows with every swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableVersion {
+    pub version: u64,
+    pub refreshed_at: DateTime<Utc>,
+}
+
+impl RefreshableTable {
+    pub fn new(table: Arc<dyn TableProvider>) -> Self {
+        let version = TableVersion {
+            version: 1,
+            refreshed_at: Utc::now(),
+        };
+        Self {
+            inner: RwLock::new((table, version)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<dyn TableProvider> {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .clone()
+    }
+
+    pub fn version(&self) -> TableVersion {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).1
+    }
+
+    pub fn swap(&self, table: Arc<dyn TableProvider>) -> Arc<dyn TableProvider> {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        let version = TableVersion {
+            version: inner.1.version + 1,
+            refreshed_at: Utc::now(),
+        };
+        std::mem::replace(&mut *inner, (table, version)).0
+    }
+}
+
+#[async_trait]
+impl TableProvider for RefreshableTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.current().schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        self.current().table_type()
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let table = self.current();
+        table.scan(state, projection, filters, limit).await
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        self.current().supports_filters_pushdown(filters)
+    }
+}
+
+/// Dedicated runtime for query execution, so CPU bound DataFusion work can not
+/// starve the HTTP runtime and its timers.
+pub fn build_query_runtime() -> Result<Runtime, LoggingTableError> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("query-runtime")
+        .enable_all()
+        .build()?;
+    Ok(runtime)
+}
+
 pub async fn write_df_to_file(df: DataFrame, file_path: &str) -> Result<(), LoggingTableError> {
-    let mut buf = vec![];
     let schema = Schema::from(df.clone().schema());
-    let mut stream = df.execute_stream().await?;
-    let mut writer = AsyncArrowWriter::try_new(&mut buf, Arc::new(schema), None)?;
+    let stream = df.execute_stream().await?;
+    let file = File::create(file_path).await?;
+    let writer = AsyncArrowWriter::try_new(file, Arc::new(schema), None)?;
+    write_batches(writer, stream).await
+}
+
+/// Encodes a stream of record batches as an in-memory Parquet file.
+pub async fn write_parquet<S>(schema: SchemaRef, stream: S) -> Result<Vec<u8>, LoggingTableError>
+where
+    S: Stream<Item = DataFusionResult<RecordBatch>> + Unpin,
+{
+    let mut buf = vec![];
+    let writer = AsyncArrowWriter::try_new(&mut buf, schema, None)?;
+    write_batches(writer, stream).await?;
+    Ok(buf)
+}
+
+async fn write_batches<W, S>(
+    mut writer: AsyncArrowWriter<W>,
+    mut stream: S,
+) -> Result<(), LoggingTableError>
+where
+    W: AsyncFileWriter,
+    S: Stream<Item = DataFusionResult<RecordBatch>> + Unpin,
+{
     while let Some(batch) = stream.next().await.transpose()? {
         writer.write(&batch).await?;
     }
     writer.close().await?;
-    let mut file = File::create(file_path).await?;
-    file.write_all(&buf).await?;
     Ok(())
 }
+
+#[derive(Debug, Clone)]
+pub struct ParquetExportOptions {
+    pub row_group_size: usize,
+    pub compression: Compression,
+    pub max_rows_per_file: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedFile {
+    pub path: String,
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+/// Streams the batches into `part-NNNNN.parquet` files under `dir`, rolling to a new
+/// file after `max_rows_per_file` rows. Nothing is left in `dir` if the export fails
+/// or is dropped before the end.
+pub async fn write_parquet_files(
+    dir: &Path,
+    mut stream: SendableRecordBatchStream,
+    options: &ParquetExportOptions,
+) -> Result<Vec<ExportedFile>, LoggingTableError> {
+    tokio::fs::create_dir_all(dir).await?;
+    let cleanup = RemoveDirOnDrop(Some(dir.to_path_buf()));
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(options.row_group_size)
+        .set_compression(options.compression)
+        .build();
+
+    let mut files = vec![];
+    let mut part: Option<ParquetPart> = None;
+    while let Some(mut batch) = stream.next().await.transpose()? {
+        while batch.num_rows() > 0 {
+            let current = match part.as_mut() {
+                Some(current) => current,
+                None => part.insert(
+                    ParquetPart::create(dir, files.len(), stream.schema(), props.clone()).await?,
+                ),
+            };
+            let len = batch
+                .num_rows()
+                .min(options.max_rows_per_file - current.rows);
+            current.writer.write(&batch.slice(0, len)).await?;
+            current.rows += len;
+            batch = batch.slice(len, batch.num_rows() - len);
+            if current.rows == options.max_rows_per_file {
+                if let Some(full) = part.take() {
+                    files.push(full.close().await?);
+                }
+            }
+        }
+    }
+    if let Some(last) = part.take() {
+        files.push(last.close().await?);
+    }
+    cleanup.keep();
+    Ok(files)
+}
+
+struct ParquetPart {
+    writer: AsyncArrowWriter<File>,
+    path: PathBuf,
+    rows: usize,
+}
+
+impl ParquetPart {
+    async fn create(
+        dir: &Path,
+        index: usize,
+        schema: SchemaRef,
+        props: WriterProperties,
+    ) -> Result<Self, LoggingTableError> {
+        let path = dir.join(format!("part-{index:05}.parquet"));
+        let file = File::create(&path).await?;
+        let writer = AsyncArrowWriter::try_new(file, schema, Some(props))?;
+        Ok(Self {
+            writer,
+            path,
+            rows: 0,
+        })
+    }
+
+    async fn close(self) -> Result<ExportedFile, LoggingTableError> {
+        self.writer.close().await?;
+        let bytes = tokio::fs::metadata(&self.path).await?.len();
+        Ok(ExportedFile {
+            path: self.path.display().to_string(),
+            rows: self.rows,
+            bytes,
+        })
+    }
+}
+
+// runs on drop so that a cancelled export cleans up too, which an async cleanup could not
+struct RemoveDirOnDrop(Option<PathBuf>);
+
+impl RemoveDirOnDrop {
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for RemoveDirOnDrop {
+    fn drop(&mut self) {
+        if let Some(dir) = self.0.take() {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                tracing::warn!(error = %e, dir = %dir.display(), "failed to remove export directory");
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use datafusion::{
+    arrow::{
+        array::{
+            Array, ArrayRef, AsArray, BooleanArray, Float64Array, Int64Array, ListBuilder,
+            StringArray, StringBuilder,
+        },
+        compute::cast,
+        datatypes::DataType,
+    },
+    common::Result as DataFusionResult,
+    logical_expr::{
+        ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, TypeSignature,
+        Volatility,
+    },
+    prelude::SessionContext,
+};
+use serde_json::Value;
+
+/// Registers the functions reading JSON log messages:
+/// - `json_get_str(message, 'path.to.field')`, `json_get_int`, `json_get_float` and
+///   `json_get_bool` return the field when it has that JSON type, NULL otherwise
+/// - `json_get_json(message, path)` returns the field serialized as JSON
+/// - `json_keys(message [, path])` returns the keys of an object
+/// - `is_json(message)` tells whether the message parses as JSON
+///
+/// Paths are dot separated object keys and array indices, e.g. `request.headers.0`,
+/// an empty path is the whole message.
+pub fn register_json_functions(ctx: &SessionContext) {
+    for (name, kind) in [
+        ("json_get_str", JsonGetKind::Str),
+        ("json_get_int", JsonGetKind::Int),
+        ("json_get_float", JsonGetKind::Float),
+        ("json_get_bool", JsonGetKind::Bool),
+        ("json_get_json", JsonGetKind::Json),
+    ] {
+        ctx.register_udf(ScalarUDF::from(JsonGet::new(name, kind)));
+    }
+    ctx.register_udf(ScalarUDF::from(JsonKeys::new()));
+    ctx.register_udf(ScalarUDF::from(IsJson::new()));
+}
+
+#[derive(Debug, Clone, Copy)]
+enum JsonGetKind {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Json,
+}
+
+#[derive(Debug)]
+struct JsonGet {
+    name: &'static str,
+    kind: JsonGetKind,
+    signature: Signature,
+}
+
+impl JsonGet {
+    fn new(name: &'static str, kind: JsonGetKind) -> Self {
+        Self {
+            name,
+            kind,
+            signature: Signature::string(2, Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for JsonGet {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        Ok(match self.kind {
+            JsonGetKind::Str | JsonGetKind::Json => DataType::Utf8,
+            JsonGetKind::Int => DataType::Int64,
+            JsonGetKind::Float => DataType::Float64,
+            JsonGetKind::Bool => DataType::Boolean,
+        })
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> DataFusionResult<ColumnarValue> {
+        let messages = string_array(&args.args[0], args.number_rows)?;
+        let paths = string_array(&args.args[1], args.number_rows)?;
+        let values = messages
+            .as_string::<i32>()
+            .iter()
+            .zip(paths.as_string::<i32>())
+            .map(|(message, path)| json_get(message?, path?));
+
+        let array: ArrayRef = match self.kind {
+            JsonGetKind::Str => Arc::new(
+                values
+                    .map(|x| Some(x?.as_str()?.to_string()))
+                    .collect::<StringArray>(),
+            ),
+            JsonGetKind::Int => Arc::new(values.map(|x| x?.as_i64()).collect::<Int64Array>()),
+            JsonGetKind::Float => Arc::new(values.map(|x| x?.as_f64()).collect::<Float64Array>()),
+            JsonGetKind::Bool => Arc::new(values.map(|x| x?.as_bool()).collect::<BooleanArray>()),
+            JsonGetKind::Json => Arc::new(
+                values
+                    .map(|x| Some(x?.to_string()))
+                    .collect::<StringArray>(),
+            ),
+        };
+        Ok(ColumnarValue::Array(array))
+    }
+}
+
+#[derive(Debug)]
+struct JsonKeys {
+    signature: Signature,
+}
+
+impl JsonKeys {
+    fn new() -> Self {
+        Self {
+            signature: Signature::one_of(
+                vec![TypeSignature::String(1), TypeSignature::String(2)],
+                Volatility::Immutable,
+            ),
+        }
+    }
+}
+
+impl ScalarUDFImpl for JsonKeys {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "json_keys"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        Ok(DataType::new_list(DataType::Utf8, true))
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> DataFusionResult<ColumnarValue> {
+        let messages = string_array(&args.args[0], args.number_rows)?;
+        let paths = match args.args.get(1) {
+            Some(path) => string_array(path, args.number_rows)?,
+            None => Arc::new(StringArray::from(vec![""; args.number_rows])),
+        };
+
+        let mut builder = ListBuilder::new(StringBuilder::new());
+        for (message, path) in messages
+            .as_string::<i32>()
+            .iter()
+            .zip(paths.as_string::<i32>())
+        {
+            match message
+                .zip(path)
+                .and_then(|(message, path)| json_get(message, path))
+            {
+                Some(Value::Object(map)) => {
+                    for key in map.keys() {
+                        builder.values().append_value(key);
+                    }
+                    builder.append(true);
+                }
+                _ => builder.append_null(),
+            }
+        }
+        Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+    }
+}
+
+#[derive(Debug)]
+struct IsJson {
+    signature: Signature,
+}
+
+impl IsJson {
+    fn new() -> Self {
+        Self {
+            signature: Signature::string(1, Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for IsJson {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "is_json"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> DataFusionResult<ColumnarValue> {
+        let messages = string_array(&args.args[0], args.number_rows)?;
+        let array: BooleanArray = messages
+            .as_string::<i32>()
+            .iter()
+            .map(|message| Some(serde_json::from_str::<serde::de::IgnoredAny>(message?).is_ok()))
+            .collect();
+        Ok(ColumnarValue::Array(Arc::new(array)))
+    }
+}
+
+// arguments may be any string type and scalars, they are all read as Utf8 arrays
+fn string_array(arg: &ColumnarValue, number_rows: usize) -> DataFusionResult<ArrayRef> {
+    let array = arg.clone().into_array(number_rows)?;
+    match array.data_type() {
+        DataType::Utf8 => Ok(array),
+        _ => Ok(cast(&array, &DataType::Utf8)?),
+    }
+}
+
+fn json_get(message: &str, path: &str) -> Option<Value> {
+    let mut value: Value = serde_json::from_str(message).ok()?;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        value = match value {
+            Value::Object(mut map) => map.remove(key)?,
+            Value::Array(mut items) => {
+                let index: usize = key.parse().ok()?;
+                if index >= items.len() {
+                    return None;
+                }
+                items.swap_remove(index)
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::common::ScalarValue;
+
+    use super::*;
+
+    const MESSAGE: &str = r#"'{"level":"error","status":504,"latency":1.5,"retry":true,"request":{"path":"/","headers":["a","b"]}}'"#;
+
+    // the value of a single row SELECT
+    async fn eval(expr: &str) -> ScalarValue {
+        let ctx = SessionContext::new();
+        register_json_functions(&ctx);
+        let batches = ctx
+            .sql(&format!("SELECT {expr}"))
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        ScalarValue::try_from_array(batches[0].column(0), 0).unwrap()
+    }
+
+    async fn eval_message(function: &str, path: &str) -> ScalarValue {
+        eval(&format!("{function}({MESSAGE}, '{path}')")).await
+    }
+
+    #[tokio::test]
+    async fn traverses_paths() {
+        let level = eval_message("json_get_str", "level").await;
+        assert_eq!(level, ScalarValue::from("error"));
+        let path = eval_message("json_get_str", "request.path").await;
+        assert_eq!(path, ScalarValue::from("/"));
+        let missing = eval_message("json_get_str", "request.method").await;
+        assert!(missing.is_null());
+        let through_scalar = eval_message("json_get_str", "level.name").await;
+        assert!(through_scalar.is_null());
+
+        let request = eval_message("json_get_json", "request.headers").await;
+        assert_eq!(request, ScalarValue::from(r#"["a","b"]"#));
+        let whole = eval("json_get_json('{\"a\": 1}', '')").await;
+        assert_eq!(whole, ScalarValue::from(r#"{"a":1}"#));
+    }
+
+    #[tokio::test]
+    async fn returns_each_json_type() {
+        assert_eq!(
+            eval_message("json_get_int", "status").await,
+            ScalarValue::Int64(Some(504))
+        );
+        assert_eq!(
+            eval_message("json_get_float", "latency").await,
+            ScalarValue::Float64(Some(1.5))
+        );
+        assert_eq!(
+            eval_message("json_get_bool", "retry").await,
+            ScalarValue::Boolean(Some(true))
+        );
+        // integers are numbers as well
+        assert_eq!(
+            eval_message("json_get_float", "status").await,
+            ScalarValue::Float64(Some(504.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn type_mismatches_are_null() {
+        for (function, path) in [
+            ("json_get_str", "status"),
+            ("json_get_int", "level"),
+            ("json_get_int", "latency"),
+            ("json_get_float", "retry"),
+            ("json_get_bool", "status"),
+            ("json_get_str", "request"),
+        ] {
+            let value = eval_message(function, path).await;
+            assert!(value.is_null(), "{function} {path}: {value:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn indexes_arrays() {
+        let first = eval_message("json_get_str", "request.headers.0").await;
+        assert_eq!(first, ScalarValue::from("a"));
+        let last = eval_message("json_get_str", "request.headers.1").await;
+        assert_eq!(last, ScalarValue::from("b"));
+        for path in [
+            "request.headers.2",
+            "request.headers.-1",
+            "request.headers.x",
+        ] {
+            assert!(eval_message("json_get_str", path).await.is_null(), "{path}");
+        }
+        let top_level = eval("json_get_int('[10, 20]', '1')").await;
+        assert_eq!(top_level, ScalarValue::Int64(Some(20)));
+    }
+
+    #[tokio::test]
+    async fn lists_object_keys() {
+        let keys = eval(&format!("json_keys({MESSAGE})")).await;
+        assert_eq!(keys.to_string(), "[level, status, latency, retry, request]");
+        let nested = eval_message("json_keys", "request").await;
+        assert_eq!(nested.to_string(), "[path, headers]");
+        // only objects have keys
+        assert!(eval_message("json_keys", "request.headers").await.is_null());
+        assert!(eval_message("json_keys", "missing").await.is_null());
+        assert!(eval("json_keys('not json')").await.is_null());
+    }
+
+    #[tokio::test]
+    async fn tells_json_apart() {
+        for (message, expected) in [
+            (MESSAGE, true),
+            ("'[1, 2]'", true),
+            ("'\"quoted\"'", true),
+            ("'42'", true),
+            ("'job 42 started'", false),
+            ("'{\"level\": '", false),
+            ("''", false),
+        ] {
+            let value = eval(&format!("is_json({message})")).await;
+            assert_eq!(value, ScalarValue::Boolean(Some(expected)), "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn null_and_invalid_inputs_are_null() {
+        for expr in [
+            "json_get_str(NULL, 'level')",
+            "json_get_str('{\"level\":\"error\"}', NULL)",
+            "json_get_int('not json', 'status')",
+            "json_get_json(NULL, '')",
+            "json_keys(NULL)",
+            "json_keys('{\"a\":{}}', NULL)",
+            "is_json(NULL)",
+        ] {
+            let value = eval(expr).await;
+            assert!(value.is_null(), "{expr}: {value:?}");
+        }
+    }
+}
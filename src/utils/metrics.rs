@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+use futures_util::TryStreamExt;
+
+// seconds, from a cached lookup to a query close to the timeout
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process wide metrics, rendered in the Prometheus text format by the `/metrics` route.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    pub query_requests: Family<u64>,
+    pub query_duration: Family<Histogram>,
+    pub query_rows: Family<u64>,
+    pub query_bytes: Family<u64>,
+    pub datafusion_errors: Family<u64>,
+    pub cloudwatch_calls: Family<u64>,
+    pub cloudwatch_retries: Family<u64>,
+    pub ingested_events: Family<u64>,
+    pub ingested_bytes: Family<u64>,
+    pub table_last_refresh: Family<f64>,
+    pub table_memory_bytes: Family<f64>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            query_requests: Family::new(
+                "cloudwatch_viewer_query_requests_total",
+                "/query requests by response status",
+                &["status"],
+            ),
+            query_duration: Family::new(
+                "cloudwatch_viewer_query_duration_seconds",
+                "/query latency by response status, streamed responses until their headers",
+                &["status"],
+            ),
+            query_rows: Family::new(
+                "cloudwatch_viewer_query_rows_total",
+                "Rows returned by /query",
+                &[],
+            ),
+            query_bytes: Family::new(
+                "cloudwatch_viewer_query_response_bytes_total",
+                "Bytes serialized in the successful /query responses",
+                &[],
+            ),
+            datafusion_errors: Family::new(
+                "cloudwatch_viewer_datafusion_errors_total",
+                "Query errors raised by DataFusion by kind",
+                &["kind"],
+            ),
+            cloudwatch_calls: Family::new(
+                "cloudwatch_viewer_cloudwatch_api_calls_total",
+                "CloudWatch Logs API calls by operation, retries excluded",
+                &["operation"],
+            ),
+            cloudwatch_retries: Family::new(
+                "cloudwatch_viewer_cloudwatch_api_retries_total",
+                "Retried CloudWatch Logs API calls by operation",
+                &["operation"],
+            ),
+            ingested_events: Family::new(
+                "cloudwatch_viewer_ingested_events_total",
+                "Log events ingested into the snapshot tables by log group",
+                &["log_group"],
+            ),
+            ingested_bytes: Family::new(
+                "cloudwatch_viewer_ingested_bytes_total",
+                "Log event bytes ingested into the snapshot tables by log group",
+                &["log_group"],
+            ),
+            table_last_refresh: Family::new(
+                "cloudwatch_viewer_table_last_refresh_timestamp_seconds",
+                "Unix time of the last successful refresh by table",
+                &["table"],
+            ),
+            table_memory_bytes: Family::new(
+                "cloudwatch_viewer_table_memory_bytes",
+                "Memory held by the in-memory tables, cached tables are read from disk",
+                &["table"],
+            ),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        self.query_requests.encode(&mut out);
+        self.query_duration.encode(&mut out);
+        self.query_rows.encode(&mut out);
+        self.query_bytes.encode(&mut out);
+        self.datafusion_errors.encode(&mut out);
+        self.cloudwatch_calls.encode(&mut out);
+        self.cloudwatch_retries.encode(&mut out);
+        self.ingested_events.encode(&mut out);
+        self.ingested_bytes.encode(&mut out);
+        self.table_last_refresh.encode(&mut out);
+        self.table_memory_bytes.encode(&mut out);
+        out
+    }
+}
+
+/// Value of one series, counters are `u64` and gauges `f64`.
+pub trait Series: Default {
+    const TYPE: &'static str;
+
+    fn encode(&self, name: &str, labels: &[(&str, &str)], out: &mut String);
+}
+
+impl Series for u64 {
+    const TYPE: &'static str = "counter";
+
+    fn encode(&self, name: &str, labels: &[(&str, &str)], out: &mut String) {
+        let _ = writeln!(out, "{name}{} {self}", format_labels(labels));
+    }
+}
+
+impl Series for f64 {
+    const TYPE: &'static str = "gauge";
+
+    fn encode(&self, name: &str, labels: &[(&str, &str)], out: &mut String) {
+        let _ = writeln!(out, "{name}{} {self}", format_labels(labels));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Histogram {
+    // per bucket, not cumulative
+    counts: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Series for Histogram {
+    const TYPE: &'static str = "histogram";
+
+    fn encode(&self, name: &str, labels: &[(&str, &str)], out: &mut String) {
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            let le = bound.to_string();
+            let labels = [labels, &[("le", le.as_str())]].concat();
+            let _ = writeln!(out, "{name}_bucket{} {cumulative}", format_labels(&labels));
+        }
+        let labels_inf = [labels, &[("le", "+Inf")]].concat();
+        let labels = format_labels(labels);
+        let _ = writeln!(
+            out,
+            "{name}_bucket{} {}",
+            format_labels(&labels_inf),
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {}", self.count);
+    }
+}
+
+/// Metric with one series per combination of label values.
+pub struct Family<T> {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    series: Mutex<BTreeMap<Vec<String>, T>>,
+}
+
+impl<T: Series> Family<T> {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        let mut series = BTreeMap::new();
+        // metrics without labels are reported from the start
+        if labels.is_empty() {
+            series.insert(vec![], T::default());
+        }
+        Self {
+            name,
+            help,
+            labels,
+            series: Mutex::new(series),
+        }
+    }
+
+    fn with(&self, values: &[&str], f: impl FnOnce(&mut T)) {
+        debug_assert_eq!(values.len(), self.labels.len(), "{}", self.name);
+        let key = values.iter().map(|x| x.to_string()).collect();
+        let mut series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        f(series.entry(key).or_default());
+    }
+
+    fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, T::TYPE);
+        let series = self.series.lock().unwrap_or_else(|e| e.into_inner());
+        for (values, value) in series.iter() {
+            let labels: Vec<_> = self
+                .labels
+                .iter()
+                .copied()
+                .zip(values.iter().map(String::as_str))
+                .collect();
+            value.encode(self.name, &labels, out);
+        }
+    }
+}
+
+impl Family<u64> {
+    pub fn inc(&self, values: &[&str]) {
+        self.inc_by(values, 1);
+    }
+
+    pub fn inc_by(&self, values: &[&str], n: u64) {
+        self.with(values, |x| *x += n);
+    }
+}
+
+impl Family<f64> {
+    pub fn set(&self, values: &[&str], value: f64) {
+        self.with(values, |x| *x = value);
+    }
+}
+
+impl Family<Histogram> {
+    pub fn observe(&self, values: &[&str], value: f64) {
+        self.with(values, |histogram| {
+            if let Some(i) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+                histogram.counts[i] += 1;
+            }
+            histogram.sum += value;
+            histogram.count += 1;
+        });
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+                .replace('\n', r"\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Counts the `/query` requests, their latency and the bytes of the successful responses.
+pub async fn record_query_metrics(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status();
+    let status_label = status.as_str();
+    metrics().query_requests.inc(&[status_label]);
+    metrics()
+        .query_duration
+        .observe(&[status_label], started.elapsed().as_secs_f64());
+    if !status.is_success() {
+        return response;
+    }
+
+    // streamed bodies are counted as they are sent, the others keep their content length
+    let (parts, body) = response.into_parts();
+    let body = match body.size_hint().exact() {
+        Some(len) => {
+            metrics().query_bytes.inc_by(&[], len);
+            body
+        }
+        None => Body::from_stream(body.into_data_stream().inspect_ok(|chunk| {
+            metrics().query_bytes.inc_by(&[], chunk.len() as u64);
+        })),
+    };
+    Response::from_parts(parts, body)
+}
@@ -1,4 +1,8 @@
 pub mod aws;
 pub mod constants;
 pub mod datafusion;
+pub mod json_functions;
+pub mod metrics;
+pub mod record_batch;
+pub mod shutdown;
 pub mod tracing;
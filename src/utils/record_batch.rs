@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use datafusion::arrow::{
+    array::{AsArray, RecordBatch},
+    compute::cast,
+    csv,
+    datatypes::{DataType, Fields, Schema, TimeUnit},
+    ipc::writer::StreamWriter,
+    json::{
+        writer::{JsonArray, LineDelimited},
+        WriterBuilder,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::logging_table::error::LoggingTableError;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+pub fn schema_columns(schema: &Schema) -> Vec<ColumnInfo> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| ColumnInfo {
+            name: field.name().to_string(),
+            data_type: field.data_type().to_string(),
+            nullable: field.is_nullable(),
+        })
+        .collect()
+}
+
+/// Casts every timestamp column to Int64 milliseconds after Jan 1, 1970 00:00:00 UTC.
+pub fn timestamps_to_epoch_millis(
+    schema: &Schema,
+    batches: &[RecordBatch],
+) -> Result<(Schema, Vec<RecordBatch>), LoggingTableError> {
+    let fields: Fields = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Timestamp(_, _) => {
+                Arc::new(field.as_ref().clone().with_data_type(DataType::Int64))
+            }
+            _ => field.clone(),
+        })
+        .collect();
+    let epoch_schema = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+
+    let mut res = vec![];
+    for batch in batches {
+        let mut columns = vec![];
+        for column in batch.columns() {
+            let column = match column.data_type() {
+                DataType::Timestamp(_, tz) => {
+                    let millis = cast(
+                        column,
+                        &DataType::Timestamp(TimeUnit::Millisecond, tz.clone()),
+                    )?;
+                    cast(&millis, &DataType::Int64)?
+                }
+                _ => column.clone(),
+            };
+            columns.push(column);
+        }
+        res.push(RecordBatch::try_new(epoch_schema.clone(), columns)?);
+    }
+    Ok((epoch_schema.as_ref().clone(), res))
+}
+
+/// Keeps the first `num_rows` rows of the batches.
+pub fn truncate_batches(batches: Vec<RecordBatch>, num_rows: usize) -> Vec<RecordBatch> {
+    let mut remaining = num_rows;
+    let mut res = vec![];
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        let len = batch.num_rows().min(remaining);
+        res.push(batch.slice(0, len));
+        remaining -= len;
+    }
+    res
+}
+
+/// Serializes the batches as one JSON object per row, keyed by column name.
+pub fn record_batches_to_rows(
+    batches: &[RecordBatch],
+) -> Result<Vec<Map<String, Value>>, LoggingTableError> {
+    let mut writer = WriterBuilder::new()
+        .with_explicit_nulls(true)
+        .build::<_, JsonArray>(Vec::new());
+    writer.write_batches(&batches.iter().collect::<Vec<_>>())?;
+    writer.finish()?;
+    let buf = writer.into_inner();
+    if buf.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+/// Serializes the batches as one JSON array per column, in schema order,
+/// so columns sharing a name (e.g. in joins) are kept apart.
+pub fn record_batches_to_columns(
+    schema: &Schema,
+    batches: &[RecordBatch],
+) -> Result<Vec<Vec<Value>>, LoggingTableError> {
+    let mut columns = vec![vec![]; schema.fields().len()];
+    for batch in batches {
+        for (i, column) in columns.iter_mut().enumerate() {
+            let rows = record_batches_to_rows(&[batch.project(&[i])?])?;
+            column.extend(
+                rows.into_iter()
+                    .map(|row| row.into_iter().next().map(|(_, v)| v).unwrap_or_default()),
+            );
+        }
+    }
+    Ok(columns)
+}
+
+pub fn record_batches_to_csv(batches: &[RecordBatch]) -> Result<Vec<u8>, LoggingTableError> {
+    let mut writer = csv::WriterBuilder::new()
+        .with_header(true)
+        .build(Vec::new());
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    Ok(writer.into_inner())
+}
+
+pub fn record_batches_to_ndjson(batches: &[RecordBatch]) -> Result<Vec<u8>, LoggingTableError> {
+    let mut writer = WriterBuilder::new()
+        .with_explicit_nulls(true)
+        .build::<_, LineDelimited>(Vec::new());
+    writer.write_batches(&batches.iter().collect::<Vec<_>>())?;
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+/// Serializes the batches as an Arrow IPC stream.
+pub fn record_batches_to_arrow(
+    schema: &Schema,
+    batches: &[RecordBatch],
+) -> Result<Vec<u8>, LoggingTableError> {
+    let mut writer = StreamWriter::try_new(Vec::new(), schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner()?)
+}
+
+/// Writes the `message` column as plain text, one line per row, skipping nulls.
+pub fn record_batches_to_text(batches: &[RecordBatch]) -> Result<Vec<u8>, LoggingTableError> {
+    let mut buf = Vec::new();
+    for batch in batches {
+        let Some(column) = batch.column_by_name("message") else {
+            return Err(LoggingTableError::MissingColumn("message".to_string()));
+        };
+        let column = cast(column, &DataType::Utf8)?;
+        for message in column.as_string::<i32>().iter().flatten() {
+            buf.extend_from_slice(message.as_bytes());
+            if !message.ends_with('\n') {
+                buf.push(b'\n');
+            }
+        }
+    }
+    Ok(buf)
+}
+
+/// Incremental encoding of streamed results, each call returns the bytes ready to be sent.
+pub enum BatchEncoder {
+    Ndjson,
+    Arrow(StreamWriter<Vec<u8>>),
+}
+
+impl BatchEncoder {
+    pub fn ndjson() -> Self {
+        Self::Ndjson
+    }
+
+    pub fn arrow(schema: &Schema) -> Result<Self, LoggingTableError> {
+        Ok(Self::Arrow(StreamWriter::try_new(Vec::new(), schema)?))
+    }
+
+    pub fn encode(&mut self, batch: &RecordBatch) -> Result<Vec<u8>, LoggingTableError> {
+        match self {
+            Self::Ndjson => record_batches_to_ndjson(std::slice::from_ref(batch)),
+            Self::Arrow(writer) => {
+                writer.write(batch)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, LoggingTableError> {
+        match self {
+            Self::Ndjson => Ok(vec![]),
+            Self::Arrow(writer) => {
+                writer.finish()?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+        }
+    }
+}
@@ -1,20 +1,26 @@
 use std::error::Error;
-use std::time::Duration;
-use std::sync::Arc;
-use std::path::Path;
 use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-use axum::{body::Body, extract::Request, response::Response};
+use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use tracing::{Level, Span};
 use tracing_error::ErrorLayer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{fmt, EnvFilter};
-use tracing::{Level, Span};
-use uuid::Uuid;
 
 use crate::error::ApiError;
 
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    // request ID of the request being handled, see scope_request_id
+    static REQUEST_ID: String;
+}
+
 pub fn init_tracing() -> Result<(), ApiError> {
     let fmt_layer = fmt::layer().compact();
     let filter_layer = EnvFilter::try_from_default_env()
@@ -32,7 +38,7 @@ pub fn init_tracing() -> Result<(), ApiError> {
 pub fn init_tracing_using_file(file_path: &str) -> Result<(), ApiError> {
     let log_file = match Path::new(file_path).exists() {
         true => File::open(file_path).map_err(|e| ApiError::UnexpectedError(e.into()))?,
-        false => File::create(file_path).map_err(|e| ApiError::UnexpectedError(e.into()))?
+        false => File::create(file_path).map_err(|e| ApiError::UnexpectedError(e.into()))?,
     };
     let file_writer = Arc::new(log_file);
     let make_writer = BoxMakeWriter::new(file_writer);
@@ -67,8 +73,39 @@ pub fn log_error_chain(e: &(dyn Error + 'static)) {
     tracing::error!("{}", report);
 }
 
+// the x-request-id header is taken from the client or generated by SetRequestIdLayer
+fn request_id(request: &Request<Body>) -> &str {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Middleware making the request ID available to `current_request_id` while the handler runs.
+pub async fn scope_request_id(request: Request<Body>, next: Next) -> Response {
+    let request_id = request_id(&request).to_string();
+    REQUEST_ID.scope(request_id, next.run(request)).await
+}
+
+/// Request ID of the request being handled, None outside of a handler.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// The error and its causes on one line, separated by colons.
+pub fn error_chain(e: &(dyn Error + 'static)) -> String {
+    let mut message = e.to_string();
+    let mut current = e.source();
+    while let Some(cause) = current {
+        message = format!("{message}: {cause}");
+        current = cause.source();
+    }
+    message
+}
+
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = Uuid::new_v4();
+    let request_id = request_id(request);
     tracing::span!(
         Level::INFO,
         "[REQUEST]",
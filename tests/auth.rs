@@ -0,0 +1,189 @@
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use cloudwatch_viewer_web_api::{
+    auth::Role,
+    config::{ApiKeyConfig, Config},
+};
+use reqwest::{Method, RequestBuilder};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use common::{spawn_app_with, test_config, TestApp};
+
+fn auth_config() -> Config {
+    let mut config = test_config();
+    config.auth.enabled = true;
+    for (name, role) in [
+        ("metrics", Role::Metrics),
+        ("reader", Role::Reader),
+        ("admin", Role::Admin),
+    ] {
+        config.auth.api_keys.push(ApiKeyConfig {
+            name: name.to_string(),
+            sha256: hex::encode(Sha256::digest(format!("{name}-key"))),
+            role,
+        });
+    }
+    config
+}
+
+fn request(app: &TestApp, method: Method, path: &str) -> RequestBuilder {
+    let request = app.client.request(method.clone(), app.url(path));
+    match method {
+        Method::POST => request.json(&json!({ "query": "SELECT 1" })),
+        _ => request,
+    }
+}
+
+async fn status(request: RequestBuilder) -> u16 {
+    request.send().await.unwrap().status().as_u16()
+}
+
+#[tokio::test]
+async fn probes_stay_open() {
+    let app = spawn_app_with(auth_config()).await;
+    for path in ["/", "/alive", "/ready"] {
+        assert_eq!(
+            status(request(&app, Method::GET, path)).await,
+            200,
+            "{path}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_without_valid_credentials_are_unauthorized() {
+    let app = spawn_app_with(auth_config()).await;
+    let response = request(&app, Method::POST, "/query").send().await.unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "unauthorized");
+
+    for request in [
+        request(&app, Method::POST, "/query").header("x-api-key", "other-key"),
+        request(&app, Method::GET, "/metrics").bearer_auth("other-key"),
+        request(&app, Method::POST, "/export").bearer_auth("not.a.jwt"),
+    ] {
+        assert_eq!(status(request).await, 401);
+    }
+}
+
+#[tokio::test]
+async fn routes_require_their_role() {
+    let app = spawn_app_with(auth_config()).await;
+    // expected status by key, for /metrics, /query and /refresh
+    for (key, expected) in [
+        ("metrics-key", [200, 403, 403]),
+        ("reader-key", [200, 200, 403]),
+        ("admin-key", [200, 200, 200]),
+    ] {
+        let statuses = [
+            status(request(&app, Method::GET, "/metrics").header("x-api-key", key)).await,
+            status(request(&app, Method::POST, "/query").bearer_auth(key)).await,
+            status(request(&app, Method::POST, "/refresh").header("x-api-key", key)).await,
+        ];
+        assert_eq!(statuses, expected, "{key}");
+    }
+
+    let response = request(&app, Method::POST, "/export")
+        .header("x-api-key", "reader-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn disabled_auth_opens_every_route() {
+    let mut config = auth_config();
+    config.auth.enabled = false;
+    let app = spawn_app_with(config).await;
+    assert_eq!(status(request(&app, Method::GET, "/metrics")).await, 200);
+    assert_eq!(status(request(&app, Method::POST, "/query")).await, 200);
+    assert_eq!(status(request(&app, Method::POST, "/refresh")).await, 200);
+}
+
+#[tokio::test]
+async fn jwts_carry_their_role() {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key =
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let point = key.public_key().as_ref();
+    let jwks = json!({ "keys": [{
+        "kty": "EC",
+        "kid": "test",
+        "alg": "ES256",
+        "crv": "P-256",
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+    }] });
+    let jwks_file = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&jwks_file, jwks.to_string()).unwrap();
+
+    let mut config = auth_config();
+    config.auth.jwks_file = Some(jwks_file.clone());
+    config.auth.jwt_audience = Some("cloudwatch-viewer".to_string());
+    let app = spawn_app_with(config).await;
+    std::fs::remove_file(&jwks_file).unwrap();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let token = |claims: Value| {
+        let header = json!({ "alg": "ES256", "kid": "test" });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = key.sign(&rng, message.as_bytes()).unwrap();
+        format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+    };
+    let claims = |role: Value, aud: &str, exp: u64| json!({ "sub": "alice", "role": role, "aud": aud, "exp": exp });
+
+    let reader = token(claims(json!("reader"), "cloudwatch-viewer", now + 300));
+    assert_eq!(
+        status(request(&app, Method::POST, "/query").bearer_auth(&reader)).await,
+        200
+    );
+    assert_eq!(
+        status(request(&app, Method::POST, "/refresh").bearer_auth(&reader)).await,
+        403
+    );
+    let admin = token(claims(
+        json!(["reader", "admin"]),
+        "cloudwatch-viewer",
+        now + 300,
+    ));
+    assert_eq!(
+        status(request(&app, Method::POST, "/refresh").bearer_auth(&admin)).await,
+        200
+    );
+    let no_role = token(claims(json!("owner"), "cloudwatch-viewer", now + 300));
+    assert_eq!(
+        status(request(&app, Method::GET, "/metrics").bearer_auth(&no_role)).await,
+        403
+    );
+    let expired = token(claims(json!("reader"), "cloudwatch-viewer", now - 3600));
+    assert_eq!(
+        status(request(&app, Method::POST, "/query").bearer_auth(&expired)).await,
+        401
+    );
+    let other_audience = token(claims(json!("reader"), "other", now + 300));
+    assert_eq!(
+        status(request(&app, Method::POST, "/query").bearer_auth(&other_audience)).await,
+        401
+    );
+}
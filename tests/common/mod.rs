@@ -0,0 +1,137 @@
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use cloudwatch_viewer_web_api::{
+    app_state::AppState,
+    config::Config,
+    log_source::MemoryLogSource,
+    logging_table::{IngestionOptions, LoggingTableRefresher, TableMode, TableStatuses},
+    utils::{datafusion::register_cloudwatch_table, json_functions::register_json_functions},
+    Application,
+};
+use datafusion::prelude::SessionContext;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+pub const FIXTURE: &str = "fixtures/demo_logs.json";
+
+pub struct TestApp {
+    pub address: String,
+    pub client: reqwest::Client,
+    pub shutdown: CancellationToken,
+}
+
+impl TestApp {
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.address)
+    }
+
+    pub async fn query(&self, body: Value) -> reqwest::Response {
+        self.client
+            .post(self.url("/query"))
+            .json(&body)
+            .send()
+            .await
+            .expect("request failed")
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+pub fn test_config() -> Config {
+    Config::test(vec![
+        "/aws/lambda/api".to_string(),
+        "/aws/lambda/worker".to_string(),
+    ])
+}
+
+/// Serves the fixture log groups, ingested into the snapshot table, on an ephemeral port.
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(test_config()).await
+}
+
+pub async fn spawn_app_with(config: Config) -> TestApp {
+    let (app, refresher) = spawn_unrefreshed_app(config).await;
+    refresher.refresh().await.expect("initial refresh");
+    app
+}
+
+/// Serves the registered but still empty snapshot table, its refresh is left to the caller.
+pub async fn spawn_unrefreshed_app(config: Config) -> (TestApp, LoggingTableRefresher) {
+    let config = Arc::new(config);
+    let ctx = SessionContext::new();
+    register_json_functions(&ctx);
+    let source = Arc::new(MemoryLogSource::from_file(FIXTURE).expect("fixture"));
+    let statuses = TableStatuses::default();
+    let refresher = LoggingTableRefresher::new(
+        ctx.clone(),
+        source.clone(),
+        &config.log_group_names,
+        &config.table.name,
+        IngestionOptions::from(&config.ingestion),
+    )
+    .with_statuses(statuses.clone());
+    refresher.register().await.expect("register table");
+
+    let app_state = AppState::new(
+        config.clone(),
+        ctx,
+        source,
+        tokio::runtime::Handle::current(),
+        statuses,
+    )
+    .with_refresher(refresher.clone());
+    let app = Application::build(&config, app_state)
+        .await
+        .expect("build application");
+    let address = app.address.clone();
+    let shutdown = app.shutdown_handle();
+    tokio::spawn(app.run());
+    let app = TestApp {
+        address,
+        client: reqwest::Client::new(),
+        shutdown: shutdown.clone(),
+    };
+    (app, refresher.with_shutdown(shutdown))
+}
+
+/// Serves the fixture log groups through the lazy table, fetched by every query.
+pub async fn spawn_lazy_app(config: Config) -> TestApp {
+    let config = Arc::new(config);
+    let ctx = SessionContext::new();
+    register_json_functions(&ctx);
+    let source = Arc::new(MemoryLogSource::from_file(FIXTURE).expect("fixture"));
+    register_cloudwatch_table(
+        &ctx,
+        source.clone(),
+        &config.log_group_names,
+        IngestionOptions::from(&config.ingestion),
+        &config.table.name,
+    )
+    .expect("register table");
+    let statuses = TableStatuses::default();
+    statuses.register(&config.table.name, TableMode::Lazy);
+    let app_state = AppState::new(
+        config.clone(),
+        ctx,
+        source,
+        tokio::runtime::Handle::current(),
+        statuses,
+    );
+    let app = Application::build(&config, app_state)
+        .await
+        .expect("build application");
+    let address = app.address.clone();
+    let shutdown = app.shutdown_handle();
+    tokio::spawn(app.run());
+    TestApp {
+        address,
+        client: reqwest::Client::new(),
+        shutdown,
+    }
+}
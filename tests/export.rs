@@ -0,0 +1,79 @@
+mod common;
+
+use std::path::Path;
+
+use datafusion::prelude::{ParquetReadOptions, SessionContext};
+use serde_json::{json, Value};
+
+use common::{spawn_app_with, test_config, TestApp};
+
+async fn export(app: &TestApp, body: Value) -> reqwest::Response {
+    app.client
+        .post(app.url("/export"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn exports_roll_over_parquet_files() {
+    let dir = std::env::temp_dir().join(format!("exports-{}", uuid::Uuid::new_v4()));
+    let mut config = test_config();
+    config.export.dir = dir.clone();
+    let app = spawn_app_with(config).await;
+
+    let response = export(
+        &app,
+        json!({ "query": "SELECT * FROM logs ORDER BY timestamp", "max_rows_per_file": 2 }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let files = body["files"].as_array().unwrap();
+    assert_eq!(body["rows"], 5);
+    assert_eq!(files.len(), 3);
+
+    // every file is readable and holds the rows it reports
+    let ctx = SessionContext::new();
+    for file in files {
+        let path = file["path"].as_str().unwrap();
+        assert!(Path::new(path).starts_with(&dir), "{path}");
+        let rows = ctx
+            .read_parquet(path, ParquetReadOptions::default())
+            .await
+            .unwrap()
+            .count()
+            .await
+            .unwrap();
+        assert_eq!(rows as u64, file["rows"].as_u64().unwrap(), "{path}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn invalid_exports_leave_nothing_behind() {
+    let dir = std::env::temp_dir().join(format!("exports-{}", uuid::Uuid::new_v4()));
+    let mut config = test_config();
+    config.export.dir = dir.clone();
+    let app = spawn_app_with(config).await;
+
+    for (body, code) in [
+        (json!({ "query": "DROP TABLE logs" }), "invalid_query"),
+        (
+            json!({ "query": "SELECT * FROM logs", "max_rows_per_file": 0 }),
+            "invalid_export_options",
+        ),
+    ] {
+        let response = export(&app, body.clone()).await;
+        assert_eq!(response.status(), 400, "{body}");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["code"], code);
+    }
+    let exports = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries.count(),
+        Err(_) => 0,
+    };
+    assert_eq!(exports, 0);
+}
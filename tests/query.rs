@@ -0,0 +1,229 @@
+mod common;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::{json, Value};
+
+use common::{spawn_app, spawn_app_with, spawn_lazy_app, test_config};
+
+#[tokio::test]
+async fn explain_is_not_limited() {
+    let app = spawn_app().await;
+    for query in [
+        "EXPLAIN SELECT * FROM logs",
+        "EXPLAIN ANALYZE SELECT count(*) FROM logs",
+    ] {
+        let response = app.query(json!({ "query": query, "page_size": 1 })).await;
+        assert_eq!(response.status(), 200, "{query}");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["truncated"], false, "{query}");
+        assert_eq!(body["next_cursor"], Value::Null, "{query}");
+        assert!(body["row_count"].as_u64().unwrap() >= 1, "{query}");
+    }
+}
+
+#[tokio::test]
+async fn only_read_only_queries_are_accepted() {
+    let app = spawn_app().await;
+    for (query, code) in [
+        ("DROP TABLE logs", "invalid_query"),
+        ("SELECT 1; SELECT 2", "invalid_query"),
+        ("COPY logs TO '/tmp/logs.csv'", "invalid_query"),
+        ("SELECT * FROM other", "plan_error"),
+        ("SELECT * FORM logs", "sql_parse_error"),
+    ] {
+        let response = app.query(json!({ "query": query })).await;
+        assert_eq!(response.status(), 400, "{query}");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["code"], code, "{query}");
+    }
+
+    // the table is still there
+    let response = app
+        .query(json!({ "query": "SELECT count(*) FROM logs" }))
+        .await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn cursors_page_through_the_results() {
+    let app = spawn_app().await;
+    let response = app
+        .query(json!({ "query": "SELECT count(*) AS n FROM logs" }))
+        .await;
+    let body: Value = response.json().await.unwrap();
+    let total = body["content"][0]["n"].as_u64().unwrap() as usize;
+    assert!(total > 3);
+
+    let query = "SELECT timestamp, log_stream_name, message FROM logs \
+        ORDER BY timestamp, log_stream_name, message";
+    let mut rows = vec![];
+    let mut cursor = Value::Null;
+    loop {
+        let response = app
+            .query(json!({ "query": query, "page_size": 3, "cursor": cursor }))
+            .await;
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        rows.extend(body["content"].as_array().unwrap().clone());
+        cursor = body["next_cursor"].clone();
+        assert_eq!(body["truncated"], !cursor.is_null());
+        if cursor.is_null() {
+            break;
+        }
+    }
+    assert_eq!(rows.len(), total);
+    let mut deduped = rows.clone();
+    deduped.dedup();
+    assert_eq!(deduped, rows);
+}
+
+#[tokio::test]
+async fn cursors_of_other_queries_are_rejected() {
+    let app = spawn_app().await;
+    let response = app
+        .query(json!({ "query": "SELECT * FROM logs", "page_size": 1 }))
+        .await;
+    let body: Value = response.json().await.unwrap();
+    let cursor = body["next_cursor"].as_str().unwrap();
+
+    let response = app
+        .query(json!({ "query": "SELECT message FROM logs", "cursor": cursor }))
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_cursor");
+}
+
+#[tokio::test]
+async fn cursors_past_the_last_offset_are_rejected() {
+    let app = spawn_app().await;
+    let query = json!({ "query": "SELECT * FROM logs", "page_size": 1 });
+    let response = app.query(query.clone()).await;
+    let body: Value = response.json().await.unwrap();
+    let cursor = URL_SAFE_NO_PAD
+        .decode(body["next_cursor"].as_str().unwrap())
+        .unwrap();
+    let mut cursor: Value = serde_json::from_slice(&cursor).unwrap();
+
+    // paged queries fetch one row more than the page
+    for (offset, stream) in [
+        (usize::MAX, true),
+        (usize::MAX, false),
+        (usize::MAX - 1, false),
+    ] {
+        cursor["offset"] = json!(offset);
+        let mut query = query.clone();
+        query["cursor"] = json!(URL_SAFE_NO_PAD.encode(cursor.to_string()));
+        query["stream"] = json!(stream);
+        query["format"] = json!("ndjson");
+        let response = app.query(query).await;
+        assert_eq!(response.status(), 400, "{offset} {stream}");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_cursor");
+    }
+}
+
+#[tokio::test]
+async fn json_functions_filter_messages() {
+    let app = spawn_app().await;
+    let query =
+        "SELECT json_get_str(message, 'msg') AS msg, json_get_int(message, 'status') AS status \
+        FROM logs WHERE json_get_str(message, 'level') = 'error'";
+    let response = app.query(json!({ "query": query })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["content"],
+        json!([{ "msg": "upstream timeout", "status": 504 }])
+    );
+
+    // plain text messages are not JSON
+    let query = "SELECT count(*) AS n FROM logs WHERE NOT is_json(message)";
+    let body: Value = app
+        .query(json!({ "query": query }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["content"][0]["n"], 2);
+}
+
+#[tokio::test]
+async fn lazy_scans_report_partial_log_groups() {
+    let mut config = test_config();
+    config.ingestion.max_events_per_stream = Some(2);
+    let app = spawn_lazy_app(config).await;
+    let response = app.query(json!({ "query": "SELECT * FROM logs" })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["row_count"], 4);
+    // the worker stream holds exactly two events
+    assert_eq!(
+        body["partial_scans"],
+        json!([{
+            "log_group_name": "/aws/lambda/api",
+            "max_streams_reached": false,
+            "truncated_streams": ["2025/01/01/[$LATEST]a1"],
+            "failed_streams": {},
+        }])
+    );
+
+    let response = app
+        .query(json!({ "query": "SELECT * FROM logs", "format": "csv" }))
+        .await;
+    assert_eq!(response.headers()["x-partial"], "true");
+}
+
+#[tokio::test]
+async fn results_follow_the_accept_header() {
+    let app = spawn_app().await;
+    let body = json!({ "query": "SELECT log_group_name FROM logs ORDER BY 1 LIMIT 1" });
+    for (accept, content_type) in [
+        ("text/csv", "text/csv"),
+        ("application/x-ndjson", "application/x-ndjson"),
+        ("text/csv;q=0.5, application/json", "application/json"),
+    ] {
+        let response = app
+            .client
+            .post(app.url("/query"))
+            .header("accept", accept)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{accept}");
+        assert_eq!(response.headers()["content-type"], content_type, "{accept}");
+    }
+
+    let response = app
+        .client
+        .post(app.url("/query"))
+        .header("accept", "application/xml")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 406);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "not_acceptable");
+}
+
+#[tokio::test]
+async fn streamed_results_are_capped_by_max_rows() {
+    let mut config = test_config();
+    config.query.max_rows = 2;
+    let app = spawn_app_with(config).await;
+    for (page_size, lines) in [(None, 2), (Some(1), 1), (Some(100), 2)] {
+        let response = app
+            .query(json!({
+                "query": "SELECT * FROM logs",
+                "format": "ndjson",
+                "stream": true,
+                "page_size": page_size,
+            }))
+            .await;
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert_eq!(body.lines().count(), lines, "{page_size:?}");
+    }
+}
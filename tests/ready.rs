@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{spawn_unrefreshed_app, test_config, TestApp};
+use serde_json::{json, Value};
+
+async fn get_ready(app: &TestApp) -> (u16, Value) {
+    let response = app
+        .client
+        .get(app.url("/ready"))
+        .send()
+        .await
+        .expect("request failed");
+    let status = response.status().as_u16();
+    (status, response.json().await.expect("json body"))
+}
+
+async fn wait_for(app: &TestApp, f: impl Fn(&Value) -> bool) -> (u16, Value) {
+    for _ in 0..100 {
+        let (status, body) = get_ready(app).await;
+        if f(&body) {
+            return (status, body);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("/ready never reached the expected state");
+}
+
+#[tokio::test]
+async fn ready_after_first_refresh() {
+    let (app, refresher) = spawn_unrefreshed_app(test_config()).await;
+
+    // the table is queryable, and empty, during the first ingestion
+    let (status, body) = get_ready(&app).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["ready"], false);
+    let response = app
+        .query(json!({ "query": "SELECT count(*) AS n FROM logs"}))
+        .await;
+    assert_eq!(response.status(), 200);
+
+    let _task = refresher.spawn(None);
+    let (status, body) = wait_for(&app, |body| body["ready"] == true).await;
+    assert_eq!(status, 200);
+    assert!(body["tables"]["logs"]["rows"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn failed_refresh_is_reported() {
+    let mut config = test_config();
+    config.log_group_names = vec!["/aws/lambda/missing".to_string()];
+    let (app, refresher) = spawn_unrefreshed_app(config).await;
+
+    let _task = refresher.spawn(None);
+    let (status, body) =
+        wait_for(&app, |body| !body["tables"]["logs"]["last_error"].is_null()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["ready"], false);
+    assert!(body["tables"]["logs"]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("/aws/lambda/missing"));
+
+    // the server keeps answering
+    let response = app
+        .query(json!({ "query": "SELECT count(*) AS n FROM logs"}))
+        .await;
+    assert_eq!(response.status(), 200);
+}